@@ -0,0 +1,178 @@
+//! Backoff strategies for delaying between retry attempts
+//!
+//! A [`Backoff`] describes how long to wait after each failed attempt, with [`Backoff::delays`]
+//! producing the sequence of delays for a single retry loop.
+//!
+//! ```
+//! use std::time::Duration;
+//! use handle_error::backoff::Backoff;
+//!
+//! let ms = Duration::from_millis;
+//!
+//! let b = Backoff::exponential(ms(10), ms(50));
+//! let d: Vec<_> = b.delays().take(5).collect();
+//! assert_eq!(d, vec![ms(10), ms(20), ms(40), ms(50), ms(50)]);
+//!
+//! let b = Backoff::fibonacci(ms(10), ms(100));
+//! let d: Vec<_> = b.delays().take(6).collect();
+//! assert_eq!(d, vec![ms(10), ms(10), ms(20), ms(30), ms(50), ms(80)]);
+//!
+//! let b = Backoff::decorrelated_jitter(ms(10), ms(100));
+//! assert!(b.delays().take(10).all(|d| d >= ms(10) && d <= ms(100)));
+//! ```
+
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+use std::collections::hash_map::RandomState;
+#[cfg(feature = "std")]
+use std::hash::{BuildHasher, Hasher};
+
+/// Strategy for computing the delay between retry attempts
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Backoff {
+    /// Retry immediately
+    #[default]
+    None,
+    /// Wait a fixed duration between attempts
+    Constant(Duration),
+    /// Increase the delay by `step` after each attempt
+    Linear { initial: Duration, step: Duration },
+    /// Double the delay after each attempt, up to `max`
+    Exponential { initial: Duration, max: Duration },
+    /// Pick a random delay between `base` and three times the previous delay, up to `max`
+    ///
+    /// See <https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/>
+    DecorrelatedJitter { base: Duration, max: Duration },
+    /// Scale `initial` by the fibonacci sequence after each attempt, up to `max`
+    Fibonacci { initial: Duration, max: Duration },
+}
+
+impl From<Duration> for Backoff {
+    fn from(d: Duration) -> Self {
+        Backoff::Constant(d)
+    }
+}
+
+impl Backoff {
+    /// Create a constant backoff
+    pub fn constant(delay: Duration) -> Self {
+        Backoff::Constant(delay)
+    }
+
+    /// Create a linear backoff
+    pub fn linear(initial: Duration, step: Duration) -> Self {
+        Backoff::Linear { initial, step }
+    }
+
+    /// Create an exponential backoff capped at `max`
+    pub fn exponential(initial: Duration, max: Duration) -> Self {
+        Backoff::Exponential { initial, max }
+    }
+
+    /// Create a decorrelated jitter backoff capped at `max`
+    pub fn decorrelated_jitter(base: Duration, max: Duration) -> Self {
+        Backoff::DecorrelatedJitter { base, max }
+    }
+
+    /// Create a fibonacci backoff capped at `max`
+    pub fn fibonacci(initial: Duration, max: Duration) -> Self {
+        Backoff::Fibonacci { initial, max }
+    }
+
+    /// Fetch an iterator over the delays for successive retry attempts
+    ///
+    /// Jitter is randomly seeded with the `std` feature, and otherwise uses a fixed seed
+    /// (see [`Backoff::delays_seeded`]).
+    pub fn delays(&self) -> Delays {
+        self.delays_seeded(seed())
+    }
+
+    /// Fetch an iterator over the delays for successive retry attempts, with jitter seeded
+    /// from the provided value (such as a hardware random number or device serial)
+    pub fn delays_seeded(&self, seed: u64) -> Delays {
+        Delays {
+            backoff: self.clone(),
+            attempt: 0,
+            prev: (Duration::from_secs(0), Duration::from_secs(0)),
+            rng: seed | 1,
+        }
+    }
+}
+
+/// Generate a random seed for jitter
+#[cfg(feature = "std")]
+fn seed() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Fixed seed for jitter where no source of randomness is available
+#[cfg(not(feature = "std"))]
+fn seed() -> u64 {
+    0x853C_49E6_748F_EA9B
+}
+
+/// Iterator over the delays produced by a [`Backoff`]
+#[derive(Clone, Debug)]
+pub struct Delays {
+    backoff: Backoff,
+    attempt: u32,
+    prev: (Duration, Duration),
+    rng: u64,
+}
+
+impl Delays {
+    /// Generate the next pseudo-random value (xorshift64*)
+    fn random(&mut self) -> u64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+impl Iterator for Delays {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let n = self.attempt;
+        self.attempt = self.attempt.saturating_add(1);
+
+        let delay = match self.backoff.clone() {
+            Backoff::None => Duration::from_secs(0),
+            Backoff::Constant(d) => d,
+            Backoff::Linear { initial, step } => step
+                .checked_mul(n)
+                .and_then(|s| initial.checked_add(s))
+                .unwrap_or(Duration::MAX),
+            Backoff::Exponential { initial, max } => 2u32
+                .checked_pow(n)
+                .and_then(|m| initial.checked_mul(m))
+                .map_or(max, |d| d.min(max)),
+            Backoff::DecorrelatedJitter { base, max } => {
+                let upper = self.prev.0.max(base).checked_mul(3).unwrap_or(max).min(max);
+                let lower = base.min(upper);
+
+                let range = (upper - lower).as_nanos().min(u64::MAX as u128) as u64;
+                let offset = match range {
+                    0 => 0,
+                    _ => self.random() % range,
+                };
+
+                let d = lower + Duration::from_nanos(offset);
+                self.prev.0 = d;
+                d
+            }
+            Backoff::Fibonacci { initial, max } => {
+                let d = match n {
+                    0 => initial,
+                    _ => self.prev.0.checked_add(self.prev.1).unwrap_or(max),
+                };
+                self.prev = (d, self.prev.0);
+                d.min(max)
+            }
+        };
+
+        Some(delay)
+    }
+}
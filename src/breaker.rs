@@ -0,0 +1,320 @@
+//! Circuit breaker for failing fast when a dependency is unavailable
+//!
+//! A [`CircuitBreaker`] counts consecutive failures of the calls routed through it, and
+//! once a threshold is reached opens to reject further calls with [`CircuitOpen`] until a
+//! cool-down has elapsed. A single trial call is then allowed (half-open), closing the
+//! breaker on success or re-opening it on failure. State transitions are logged through
+//! the selected logging backend.
+//!
+//! Retries may be routed through a breaker with [`CircuitBreaker::retry`], the `breaker`
+//! option to [`retry_error!`](crate::retry_error), or [`RetryPolicy::breaker`], so that
+//! retrying stops once the breaker opens.
+//!
+//! ```
+//! use std::io::{Error, ErrorKind};
+//! use std::time::Duration;
+//! use handle_error::breaker::{CircuitBreaker, CircuitOpen, State};
+//! use handle_error::clock::ManualClock;
+//!
+//! let clock = ManualClock::new();
+//! let breaker = CircuitBreaker::new(2, Duration::from_secs(30)).clock(&clock);
+//!
+//! for _ in 0..2 {
+//!     let r: Result<(), Error> = breaker.call(|| Err(ErrorKind::TimedOut.into()));
+//!     assert_eq!(r.unwrap_err().kind(), ErrorKind::TimedOut);
+//! }
+//! assert_eq!(breaker.state(), State::Open);
+//!
+//! // Calls fail fast while the breaker is open
+//! let r: Result<(), Error> = breaker.call(|| Ok(()));
+//! assert!(r.unwrap_err().get_ref().unwrap().is::<CircuitOpen>());
+//!
+//! // And a successful trial call following the cool-down closes the breaker
+//! clock.advance(Duration::from_secs(30));
+//! assert_eq!(breaker.state(), State::HalfOpen);
+//! let r: Result<(), Error> = breaker.call(|| Ok(()));
+//! assert!(r.is_ok());
+//! assert_eq!(breaker.state(), State::Closed);
+//! ```
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::retry::{RetryHook, RetryPolicy, RetryPredicate, StopReason};
+
+/// State of a [`CircuitBreaker`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    /// Calls are allowed, with failures counted towards the threshold
+    Closed,
+    /// Calls are rejected until the cool-down has elapsed
+    Open,
+    /// A single trial call is allowed to determine whether to close the breaker
+    HalfOpen,
+}
+
+impl Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::Closed => write!(f, "closed"),
+            State::Open => write!(f, "open"),
+            State::HalfOpen => write!(f, "half-open"),
+        }
+    }
+}
+
+/// Error returned for calls rejected by an open [`CircuitBreaker`]
+///
+/// Errors returned from [`CircuitBreaker::call`] are converted from this using `From`,
+/// which is implemented for `std::io::Error` (as [`std::io::ErrorKind::Other`]) and
+/// `Box<dyn Error>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CircuitOpen;
+
+impl Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "circuit breaker open")
+    }
+}
+
+impl Error for CircuitOpen {}
+
+impl From<CircuitOpen> for std::io::Error {
+    fn from(e: CircuitOpen) -> Self {
+        std::io::Error::other(e)
+    }
+}
+
+/// Circuit breaker rejecting calls once a threshold of consecutive failures is reached,
+/// see the [module documentation](self)
+///
+/// This may be shared between threads, for example in an `Arc` or `static` (as
+/// [`CircuitBreaker::new`] is `const`).
+#[derive(Debug)]
+pub struct CircuitBreaker<C = SystemClock> {
+    threshold: u32,
+    cool_down: Duration,
+    clock: C,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: State,
+    failures: u32,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    /// Create a new (closed) circuit breaker, opening after `threshold` consecutive failures
+    /// and allowing a trial call once `cool_down` has elapsed
+    pub const fn new(threshold: u32, cool_down: Duration) -> Self {
+        Self {
+            threshold,
+            cool_down,
+            clock: SystemClock,
+            inner: Mutex::new(Inner {
+                state: State::Closed,
+                failures: 0,
+                opened_at: None,
+            }),
+        }
+    }
+}
+
+impl<C: Clock> CircuitBreaker<C> {
+    /// Set the clock used to time the cool-down
+    pub fn clock<C2: Clock>(self, clock: C2) -> CircuitBreaker<C2> {
+        CircuitBreaker {
+            threshold: self.threshold,
+            cool_down: self.cool_down,
+            clock,
+            inner: self.inner,
+        }
+    }
+
+    /// Fetch the current state of the breaker
+    ///
+    /// An open breaker is reported as half-open once the cool-down has elapsed, with the
+    /// transition made (and logged) by the next call.
+    pub fn state(&self) -> State {
+        let inner = self.inner.lock().unwrap();
+        match inner.state {
+            State::Open if self.cooled_down(&inner) => State::HalfOpen,
+            s => s,
+        }
+    }
+
+    /// Fetch the number of consecutive failures
+    pub fn failures(&self) -> u32 {
+        self.inner.lock().unwrap().failures
+    }
+
+    /// Call the provided fallible function through the breaker, returning [`CircuitOpen`]
+    /// (converted using `From`) without calling the function where the breaker is open
+    pub fn call<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: From<CircuitOpen>,
+    {
+        let trial = self.acquire()?;
+
+        // Record a failure should the call panic, so a half-open breaker is not left
+        // waiting for a trial call that will never complete
+        let mut pending = Pending { breaker: self, done: false };
+        if trial {
+            crate::__log!(info, "Circuit breaker half-open, allowing trial call");
+        }
+        let r = f();
+        pending.done = true;
+
+        self.record(r.is_ok());
+        r
+    }
+
+    /// Run the provided fallible function under a [`RetryPolicy`], with each attempt
+    /// routed through the breaker and retrying stopped once the breaker opens
+    ///
+    /// ```
+    /// use std::io::{Error, ErrorKind};
+    /// use std::time::Duration;
+    /// use handle_error::breaker::{CircuitBreaker, State};
+    /// use handle_error::retry::RetryPolicy;
+    ///
+    /// let breaker = CircuitBreaker::new(3, Duration::from_secs(30));
+    ///
+    /// let mut attempts = 0;
+    /// let r: Result<(), Error> = breaker.retry(RetryPolicy::new(10), || {
+    ///     attempts += 1;
+    ///     Err(ErrorKind::TimedOut.into())
+    /// });
+    ///
+    /// assert!(r.is_err());
+    /// assert_eq!(attempts, 3);
+    /// assert_eq!(breaker.state(), State::Open);
+    /// ```
+    pub fn retry<T, E, F, P, C2, H>(&self, policy: RetryPolicy<P, C2, H>, mut f: F) -> Result<T, E>
+    where
+        F: FnMut() -> Result<T, E>,
+        E: From<CircuitOpen>,
+        P: RetryPredicate<E>,
+        C2: Clock,
+        H: RetryHook<E>,
+    {
+        policy.breaker(self).run(|| self.call(&mut f))
+    }
+
+    /// Check whether a call would currently be allowed
+    fn accepting(&self) -> bool {
+        let inner = self.inner.lock().unwrap();
+        match inner.state {
+            State::Closed => true,
+            State::Open => self.cooled_down(&inner),
+            State::HalfOpen => false,
+        }
+    }
+
+    fn cooled_down(&self, inner: &Inner) -> bool {
+        match inner.opened_at {
+            Some(t) => self.clock.now().saturating_duration_since(t) >= self.cool_down,
+            None => true,
+        }
+    }
+
+    // Transitions are logged once the lock is released, so a logger that panics or calls
+    // through the breaker can not poison or deadlock it
+
+    /// Acquire permission for a call, returning whether it is a half-open trial call
+    fn acquire(&self) -> Result<bool, CircuitOpen> {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            State::Closed => Ok(false),
+            State::Open if self.cooled_down(&inner) => {
+                inner.state = State::HalfOpen;
+                Ok(true)
+            }
+            State::Open | State::HalfOpen => Err(CircuitOpen),
+        }
+    }
+
+    fn record(&self, success: bool) {
+        let mut inner = self.inner.lock().unwrap();
+
+        if success {
+            let previous = inner.state;
+            inner.state = State::Closed;
+            inner.failures = 0;
+            drop(inner);
+
+            if previous != State::Closed {
+                crate::__log!(info, "Circuit breaker closed");
+            }
+            return;
+        }
+
+        inner.failures = inner.failures.saturating_add(1);
+        let failures = inner.failures;
+        let reopened = match inner.state {
+            State::HalfOpen => true,
+            State::Closed if failures >= self.threshold => false,
+            _ => return,
+        };
+        inner.state = State::Open;
+        inner.opened_at = Some(self.clock.now());
+        drop(inner);
+
+        if reopened {
+            crate::__log!(warn, "Circuit breaker re-opened after failed trial call");
+        } else {
+            crate::__log!(warn, "Circuit breaker opened after {} consecutive failures", failures);
+        }
+    }
+}
+
+/// Guard recording a failed call where the called function panics
+struct Pending<'a, C: Clock> {
+    breaker: &'a CircuitBreaker<C>,
+    done: bool,
+}
+
+impl<'a, C: Clock> Drop for Pending<'a, C> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.breaker.record(false);
+        }
+    }
+}
+
+/// Retry predicate stopping retries while a [`CircuitBreaker`] is rejecting calls, see
+/// [`RetryPolicy::breaker`]
+#[derive(Debug)]
+pub struct WhileClosed<'a, P, C> {
+    breaker: &'a CircuitBreaker<C>,
+    predicate: P,
+}
+
+impl<'a, P, C> WhileClosed<'a, P, C> {
+    pub(crate) fn new(breaker: &'a CircuitBreaker<C>, predicate: P) -> Self {
+        Self { breaker, predicate }
+    }
+}
+
+impl<'a, E, P, C> RetryPredicate<E> for WhileClosed<'a, P, C>
+where
+    P: RetryPredicate<E>,
+    C: Clock,
+{
+    fn should_retry(&self, error: &E) -> bool {
+        self.breaker.accepting() && self.predicate.should_retry(error)
+    }
+
+    fn stop_reason(&self, error: &E) -> Option<StopReason> {
+        if !self.breaker.accepting() {
+            return Some(StopReason::CircuitOpen);
+        }
+        self.predicate.stop_reason(error)
+    }
+}
@@ -0,0 +1,96 @@
+//! Clocks used for timing and sleeping between blocking retry attempts
+//!
+//! [`SystemClock`] uses the system time and sleeps the current thread, while
+//! [`ManualClock`] only advances when slept or explicitly advanced, to allow
+//! deterministic tests without real delays.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Source of time for blocking retries
+pub trait Clock {
+    /// Fetch the current instant
+    fn now(&self) -> Instant;
+
+    /// Block for the provided duration
+    fn sleep(&self, duration: Duration);
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration)
+    }
+}
+
+/// Clock using [`Instant::now`] and [`std::thread::sleep`]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+/// Clock that is advanced manually or by sleeping, for use in tests
+///
+/// ```
+/// use std::time::Duration;
+/// use handle_error::clock::{Clock, ManualClock};
+///
+/// let clock = ManualClock::new();
+/// let start = clock.now();
+///
+/// clock.sleep(Duration::from_secs(10));
+/// assert_eq!(clock.now() - start, Duration::from_secs(10));
+/// ```
+#[derive(Debug)]
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    /// Create a new manual clock starting at the current instant
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Mutex::new(Duration::from_secs(0)),
+        }
+    }
+
+    /// Advance the clock by the provided duration
+    pub fn advance(&self, duration: Duration) {
+        let mut elapsed = self.elapsed.lock().unwrap();
+        *elapsed += duration;
+    }
+
+    /// Fetch the total duration the clock has been advanced by
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+}
@@ -0,0 +1,264 @@
+//! Errors carrying the context of the site they were handled at
+//!
+//! See [`context_error!`](crate::context_error) for use, and [`ErrorChain`] for
+//! formatting errors with their sources.
+//!
+//! Messages are stored as a `String` with the `alloc` feature, or otherwise in a
+//! [`FixedMessage`] so context errors may be created without allocating.
+
+use core::error::Error;
+use core::fmt::{self, Display, Write};
+
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+
+/// Source location of a handled error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location {
+    /// Source file
+    pub file: &'static str,
+    /// Line number
+    pub line: u32,
+    /// Column number
+    pub column: u32,
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+impl From<&core::panic::Location<'static>> for Location {
+    fn from(l: &core::panic::Location<'static>) -> Self {
+        Self {
+            file: l.file(),
+            line: l.line(),
+            column: l.column(),
+        }
+    }
+}
+
+/// Message type used by [`context_error!`](crate::context_error) and
+/// [`ResultExt::context_log`](crate::ext::ResultExt::context_log)
+#[cfg(feature = "alloc")]
+pub type DefaultMessage = String;
+
+/// Message type used by [`context_error!`](crate::context_error) and
+/// [`ResultExt::context_log`](crate::ext::ResultExt::context_log)
+#[cfg(not(feature = "alloc"))]
+pub type DefaultMessage = FixedMessage<64>;
+
+/// Message that may be formatted for a [`ContextError`]
+pub trait Message: AsRef<str> {
+    /// Create a message from the provided format arguments
+    fn from_args(args: fmt::Arguments<'_>) -> Self;
+}
+
+#[cfg(feature = "alloc")]
+impl Message for String {
+    fn from_args(args: fmt::Arguments<'_>) -> Self {
+        alloc::fmt::format(args)
+    }
+}
+
+impl<const N: usize> Message for FixedMessage<N> {
+    fn from_args(args: fmt::Arguments<'_>) -> Self {
+        let mut m = Self::new();
+        let _ = m.write_fmt(args);
+        m
+    }
+}
+
+/// Message stored in a fixed capacity buffer of `N` bytes, for use without an allocator
+///
+/// Messages exceeding the capacity are truncated at a character boundary, with any
+/// further writes discarded.
+///
+/// ```
+/// use core::fmt::Write;
+/// use handle_error::context::FixedMessage;
+///
+/// let mut m = FixedMessage::<12>::new();
+/// write!(m, "read failed: {}", 42).unwrap();
+///
+/// assert_eq!(m.as_str(), "read failed:");
+/// assert!(m.is_truncated());
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct FixedMessage<const N: usize> {
+    buff: [u8; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> FixedMessage<N> {
+    /// Create a new empty message
+    pub const fn new() -> Self {
+        Self {
+            buff: [0; N],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    /// Fetch the message as a string
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buff[..self.len]).unwrap_or_default()
+    }
+
+    /// Check whether the message was truncated to fit the capacity
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl<const N: usize> Default for FixedMessage<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Write for FixedMessage<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.truncated {
+            return Ok(());
+        }
+
+        let mut n = s.len().min(N - self.len);
+        if n < s.len() {
+            self.truncated = true;
+            while !s.is_char_boundary(n) {
+                n -= 1;
+            }
+        }
+
+        self.buff[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+
+        Ok(())
+    }
+}
+
+impl<const N: usize> AsRef<str> for FixedMessage<N> {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> Display for FixedMessage<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<const N: usize> fmt::Debug for FixedMessage<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// Error wrapping a source error with a message and the location it was handled at
+///
+/// The message is a [`DefaultMessage`] unless otherwise specified, such as a
+/// [`FixedMessage`] of a different capacity.
+///
+/// ```
+/// use handle_error::context::{ContextError, FixedMessage, Location, Message};
+///
+/// let location = Location { file: "main.rs", line: 1, column: 1 };
+/// let message = FixedMessage::<32>::from_args(format_args!("write to {:#x} failed", 0x40));
+/// let e = ContextError::new(message, location, "bus fault");
+///
+/// assert_eq!(e.to_string(), "write to 0x40 failed (main.rs:1:1)");
+/// ```
+#[derive(Debug)]
+pub struct ContextError<E, M = DefaultMessage> {
+    message: M,
+    location: Location,
+    source: E,
+}
+
+impl<E, M: AsRef<str>> ContextError<E, M> {
+    /// Create a new context error
+    pub fn new(message: M, location: Location, source: E) -> Self {
+        Self {
+            message,
+            location,
+            source,
+        }
+    }
+
+    /// Fetch the context message
+    pub fn message(&self) -> &str {
+        self.message.as_ref()
+    }
+
+    /// Fetch the location the error was handled at
+    pub fn location(&self) -> Location {
+        self.location
+    }
+
+    /// Fetch a reference to the source error
+    pub fn get_ref(&self) -> &E {
+        &self.source
+    }
+
+    /// Consume the context error, returning the source error
+    pub fn into_inner(self) -> E {
+        self.source
+    }
+}
+
+impl<E, M: AsRef<str>> Display for ContextError<E, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message.as_ref(), self.location)
+    }
+}
+
+impl<E: Error + 'static, M: AsRef<str> + fmt::Debug> Error for ContextError<E, M> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Display adaptor formatting an error followed by its chain of sources, separated by `: `
+///
+/// ```
+/// use handle_error::context::{ContextError, ErrorChain, Location};
+///
+/// let location = Location { file: "main.rs", line: 1, column: 1 };
+/// let inner = std::io::Error::new(std::io::ErrorKind::Other, "device unplugged");
+/// let e = ContextError::new("read failed".to_string(), location, inner);
+///
+/// assert_eq!(ErrorChain(&e).to_string(), "read failed (main.rs:1:1): device unplugged");
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct ErrorChain<'a>(pub &'a (dyn Error + 'static));
+
+impl<'a> Display for ErrorChain<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)?;
+
+        let mut source = self.0.source();
+        while let Some(s) = source {
+            write!(f, ": {}", s)?;
+            source = s.source();
+        }
+
+        Ok(())
+    }
+}
+
+/// Fetch the location of the current call site
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __here {
+    () => (
+        $crate::context::Location {
+            file: file!(),
+            line: line!(),
+            column: column!(),
+        }
+    );
+}
@@ -0,0 +1,178 @@
+//! Extension traits for handling errors in method chains
+//!
+//! These log through the same backend as [`handle_error!`](crate::handle_error), at the
+//! default level (unless otherwise noted) and with the error (using `Debug`) and the
+//! location of the caller, returning the `Result` or `Option` so they can be followed by `?`.
+//!
+//! ```
+//! use handle_error::ext::{OptionExt, ResultExt};
+//! use handle_error::context::ContextError;
+//!
+//! fn parse(s: &str) -> Result<u32, std::num::ParseIntError> {
+//!     let v = s.parse::<u32>().log_err("Failed to parse value")?;
+//!     Ok(v)
+//! }
+//!
+//! fn open(path: &str) -> Result<std::fs::File, ContextError<std::io::Error>> {
+//!     let f = std::fs::File::open(path).context_log("Failed to open file")?;
+//!     Ok(f)
+//! }
+//!
+//! fn first(v: &[u32]) -> Option<u32> {
+//!     let f = v.first().log_none("List is empty")?;
+//!     Some(*f)
+//! }
+//!
+//! assert!(parse("nope").is_err());
+//! assert_eq!(open("/does/not/exist").unwrap_err().message(), "Failed to open file");
+//! assert_eq!(first(&[]), None);
+//! ```
+
+use core::fmt::Debug;
+use core::panic::Location as CallerLocation;
+
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+
+use crate::context::{ContextError, DefaultMessage, Message};
+
+/// Extension methods for logging errors from a `Result`
+pub trait ResultExt<T, E> {
+    /// Log the provided message on error
+    fn log_err(self, msg: &str) -> Result<T, E>;
+
+    /// Log the message returned by the provided function on error
+    ///
+    /// ```
+    /// use handle_error::ext::ResultExt;
+    ///
+    /// let r = "nope".parse::<u32>().log_err_with(|e| format!("Failed to parse value: {}", e));
+    /// assert!(r.is_err());
+    /// ```
+    #[cfg(feature = "alloc")]
+    fn log_err_with<F: FnOnce(&E) -> String>(self, f: F) -> Result<T, E>;
+
+    /// Log the provided message at `warn` level on error
+    fn log_warn(self, msg: &str) -> Result<T, E>;
+
+    /// Log the provided message on error, wrapping the error with the message and caller
+    /// location as a [`ContextError`] (see [`context_error!`](crate::context_error))
+    fn context_log(self, msg: &str) -> Result<T, ContextError<E>>;
+
+    /// Unwrap the value, or log the provided message and return `T::default()` on error
+    ///
+    /// ```
+    /// use handle_error::ext::ResultExt;
+    ///
+    /// let n: u32 = "nope".parse().unwrap_or_log("Failed to parse count");
+    /// assert_eq!(n, 0);
+    /// ```
+    fn unwrap_or_log(self, msg: &str) -> T
+    where
+        T: Default;
+}
+
+impl<T, E: Debug> ResultExt<T, E> for Result<T, E> {
+    #[track_caller]
+    fn log_err(self, msg: &str) -> Result<T, E> {
+        if let Err(e) = &self {
+            crate::__log_caller!(default, msg, e, CallerLocation::caller());
+        }
+        self
+    }
+
+    #[cfg(feature = "alloc")]
+    #[track_caller]
+    fn log_err_with<F: FnOnce(&E) -> String>(self, f: F) -> Result<T, E> {
+        if let Err(e) = &self {
+            let msg = f(e);
+            crate::__log_caller!(default, msg.as_str(), e, CallerLocation::caller());
+        }
+        self
+    }
+
+    #[track_caller]
+    fn log_warn(self, msg: &str) -> Result<T, E> {
+        if let Err(e) = &self {
+            crate::__log_caller!(warn, msg, e, CallerLocation::caller());
+        }
+        self
+    }
+
+    #[track_caller]
+    fn context_log(self, msg: &str) -> Result<T, ContextError<E>> {
+        match self {
+            Ok(v) => Ok(v),
+            Err(e) => {
+                let location = CallerLocation::caller();
+                crate::__log_caller!(default, msg, e, location);
+                let message = DefaultMessage::from_args(format_args!("{}", msg));
+                Err(ContextError::new(message, location.into(), e))
+            }
+        }
+    }
+
+    #[track_caller]
+    fn unwrap_or_log(self, msg: &str) -> T
+    where
+        T: Default,
+    {
+        match self {
+            Ok(v) => v,
+            Err(e) => {
+                crate::__log_caller!(default, msg, e, CallerLocation::caller());
+                T::default()
+            }
+        }
+    }
+}
+
+/// Extension methods for logging `None` from an `Option`
+pub trait OptionExt<T> {
+    /// Log the provided message on `None`
+    fn log_none(self, msg: &str) -> Option<T>;
+
+    /// Log the provided message on `None`, returning the provided error
+    /// (see [`handle_none!`](crate::handle_none))
+    fn ok_or_log<E>(self, err: E, msg: &str) -> Result<T, E>;
+
+    /// Unwrap the value, or log the provided message and return `T::default()` on `None`
+    fn unwrap_or_log(self, msg: &str) -> T
+    where
+        T: Default;
+}
+
+impl<T> OptionExt<T> for Option<T> {
+    #[track_caller]
+    fn log_none(self, msg: &str) -> Option<T> {
+        if self.is_none() {
+            crate::__log_caller!(default, msg, CallerLocation::caller());
+        }
+        self
+    }
+
+    #[track_caller]
+    fn ok_or_log<E>(self, err: E, msg: &str) -> Result<T, E> {
+        match self {
+            Some(v) => Ok(v),
+            None => {
+                crate::__log_caller!(default, msg, CallerLocation::caller());
+                Err(err)
+            }
+        }
+    }
+
+    #[track_caller]
+    fn unwrap_or_log(self, msg: &str) -> T
+    where
+        T: Default,
+    {
+        match self {
+            Some(v) => v,
+            None => {
+                crate::__log_caller!(default, msg, CallerLocation::caller());
+                T::default()
+            }
+        }
+    }
+}
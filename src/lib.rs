@@ -15,11 +15,7 @@
 //! This can be used as follows:
 //! 
 //! ```no_run
-//! #[macro_use]
-//! extern crate log;
-//! 
-//! #[macro_use]
-//! extern crate handle_error;
+//! use handle_error::handle_error;
 //! 
 //! # type E = ();
 //! #
@@ -33,11 +29,7 @@
 //! }
 //! ```
 //! ```no_run
-//! #[macro_use]
-//! extern crate log;
-//! 
-//! #[macro_use]
-//! extern crate handle_error;
+//! use handle_error::retry_error;
 //! 
 //! # type E = ();
 //! #
@@ -57,9 +49,6 @@
 //! #[macro_use]
 //! extern crate log;
 //! 
-//! #[macro_use]
-//! extern crate handle_error;
-//! 
 //! # type E = ();
 //! # fn do_something() -> Result<(), E> {
 //! #     unimplemented!()
@@ -90,69 +79,792 @@
 //! 
 //! # fn main() {}
 //! ```
+//!
+//! ## Logging backends
+//!
+//! Messages are emitted through the logging backend selected by cargo features, so
+//! call sites do not need to import any logging macros:
+//!
+//! - `log` (default) logs via the [log](https://docs.rs/log) facade
+//! - `tracing` emits [tracing](https://docs.rs/tracing) events
+//! - `defmt` logs via [defmt](https://docs.rs/defmt) for embedded targets
+//! - `eprintln` writes messages to stderr
+//!
+//! Where more than one backend is enabled `tracing` is preferred, followed by `log`,
+//! `defmt` and `eprintln`, and with none enabled messages are discarded.
+//!
+//! ## Logging levels
+//!
+//! Handled errors are logged at `error` level by default, with failed retry attempts
+//! logged at `warn` (or the default level, where it has been lowered below `warn`). The
+//! default may be lowered crate-wide with the `default-level-warn`, `default-level-info`,
+//! `default-level-debug` or `default-level-trace` features (where more than one is enabled
+//! the most severe is used), or set per invocation with `level = ...`.
+//!
+//! Where a call site fails repeatedly its messages may be rate limited, see [`limit`],
+//! and counters of the errors and retries at each call site are available from [`stats`].
+//!
+//! With the `location` feature (enabled by default) the file, line, column and module of
+//! the call site are appended to logged messages, or recorded as fields with `tracing`.
+//! This may be disabled to reduce binary size.
+//!
+//! ## `no_std`
+//!
+//! The crate is `no_std` with the `std` feature (enabled by default) disabled, for use on
+//! embedded targets with the `log` or `defmt` backends. The `tracing` backend enables
+//! `std` through tracing's default features, so is not available here. The `std` feature
+//! enables the [`retry`] policies, [`clock`]s, circuit [`breaker`], rate [`limit`]ing, call
+//! site [`stats`] and [panic](mod@panic) catching, and implies `alloc`.
+//!
+//! Without `std`, `retry_error!` retries immediately and supports only the retry limit,
+//! `|attempt| ...` expressions and logging. With `alloc`, [`context::ContextError`] messages
+//! are stored as a `String`, and without they are formatted into a fixed capacity
+//! [`context::FixedMessage`] so no allocation is required. With `defmt` the messages passed
+//! to `context_error!` are formatted with `core::fmt` rather than `defmt`, so must only use
+//! `{}` parameters.
+//!
+//! ```toml
+//! [dependencies]
+//! handle-error = { version = "0.1", default-features = false, features = ["defmt"] }
+//! ```
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+mod logging;
+
+pub mod backoff;
+#[cfg(feature = "std")]
+pub mod breaker;
+#[cfg(feature = "std")]
+pub mod clock;
+pub mod context;
+pub mod ext;
+#[cfg(feature = "std")]
+pub mod limit;
+#[cfg(feature = "std")]
+pub mod panic;
+#[cfg(feature = "std")]
+pub mod retry;
+#[cfg(feature = "std")]
+pub mod stats;
+
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(feature = "log")]
+    pub use log;
+
+    #[cfg(feature = "tracing")]
+    pub use tracing;
+
+    #[cfg(feature = "defmt")]
+    pub use defmt;
+
+    /// Call site counters are not maintained without the `std` feature
+    #[cfg(not(feature = "std"))]
+    pub struct NoStats;
+
+    #[cfg(not(feature = "std"))]
+    impl NoStats {
+        pub fn failure(&self) {}
+
+        pub fn retried(&self, _attempts: u32, _success: bool) {}
+    }
+}
+
+/// Call site counters are not maintained without the `std` feature
+#[cfg(not(feature = "std"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __site_stats {
+    ($name:ident) => ($crate::__private::NoStats);
+}
 
 /// Log and propagate the error result from a given expression
 ///
 /// This logs the provided message and exits the function scope on error, and returns
 /// the unpacked Ok(value) on success.
+///
+/// As with `?` the returned error is converted using `From`, so this may be used in
+/// functions returning `Box<dyn Error>` or other error types. A mapping function may
+/// also be applied to the error (prior to conversion) with `map = |e| ...`, or an error
+/// constructor such as an enum variant with `expr => Error::Variant`. In both cases the
+/// original error is logged and the mapped error returned.
+///
+/// Additional `key = value` fields may follow the message after a `;`. With the `tracing`
+/// backend these are recorded on the emitted event alongside the failed expression (as
+/// `expr`) and, where `display`, `debug` or `chain` is selected, the error (as `error`,
+/// recorded as a `dyn Error` with `chain`). As with `tracing`, values are recorded as
+/// [`tracing::Value`](https://docs.rs/tracing/latest/tracing/trait.Value.html)s, or using
+/// their `Display` or `Debug` implementations with `key = %value` or `key = ?value`.
+/// Other backends append the fields to the message as `key=value` using `Display` for
+/// `%value` and `Debug` otherwise (with `defmt` discarding them).
+///
+/// Messages are logged at the default level (see [Logging levels](crate#logging-levels)),
+/// or the level may be set per invocation with `level = warn` (or `info`, `debug`, `trace`)
+/// before the expression, or using the `handle_warn!`, `handle_info!` and `handle_debug!`
+/// shorthands. This also applies to `handle_none!` and `context_error!`.
+///
+/// Rather than returning, a fallback value may be provided before the message with
+/// `default = value`, or computed with `or_else = || ...`, in which case the error is
+/// logged and the macro evaluates to the fallback. See also
+/// [`ResultExt::unwrap_or_log`](ext::ResultExt::unwrap_or_log).
+///
+/// Within loops, `continue` or `break` (optionally with a label such as `continue 'outer`)
+/// may be passed before the message to log the error and skip the item or exit the loop.
+///
+/// With the `catch_unwind` option (following the expression) panics in the expression are
+/// caught and handled as errors, see [`panic`].
+///
+/// The error itself may be appended to the message by passing `display`, `debug` or `chain`
+/// (for the error and its [sources](std::error::Error::source), see [`context::ErrorChain`])
+/// before the message. With `tracing` these select how the `error` field is recorded.
+///
+/// ```no_run
+/// use handle_error::handle_error;
+///
+/// fn open(path: &str) -> Result<std::fs::File, std::io::Error> {
+///     let f = handle_error!(std::fs::File::open(path), "Failed to open file"; path = path);
+///     Ok(f)
+/// }
+///
+/// fn read(f: &mut std::fs::File) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+///     let mut buff = vec![];
+///     handle_error!(std::io::Read::read_to_end(f, &mut buff), display, "Failed to read file");
+///     Ok(buff)
+/// }
+///
+/// fn read_config(path: &str) -> Result<String, std::io::Error> {
+///     let s = handle_error!(level = warn, std::fs::read_to_string(path), "No config at {}", path);
+///     Ok(s)
+/// }
+///
+/// fn read_timeout(path: &str) -> u64 {
+///     let s = handle_error!(std::fs::read_to_string(path), or_else = String::new, "Failed to read {}", path);
+///     handle_error!(s.trim().parse(), default = 30, "Invalid timeout {}", s)
+/// }
+///
+/// fn read_string(f: &mut std::fs::File) -> Result<String, String> {
+///     let mut s = String::new();
+///     handle_error!(std::io::Read::read_to_string(f, &mut s), map = |e: std::io::Error| e.to_string(),
+///         "Failed to read file");
+///     Ok(s)
+/// }
+/// ```
+///
+/// ```
+/// use handle_error::handle_error;
+///
+/// #[derive(Debug)]
+/// enum DeviceError {
+///     Io(std::io::Error),
+///     Timeout,
+/// }
+///
+/// fn connect() -> Result<(), std::io::Error> {
+///     Err(std::io::ErrorKind::TimedOut.into())
+/// }
+///
+/// fn open() -> Result<(), DeviceError> {
+///     handle_error!(connect() => DeviceError::Io, "Failed to connect");
+///     Ok(())
+/// }
+///
+/// fn open_timeout() -> Result<(), DeviceError> {
+///     handle_error!(connect(), map = |_| DeviceError::Timeout, "Failed to connect");
+///     Ok(())
+/// }
+///
+/// fn retries() -> u32 {
+///     handle_error!(connect().map(|_| 5), default = 3, "Failed to connect")
+/// }
+///
+/// assert!(matches!(open(), Err(DeviceError::Io(_))));
+/// assert!(matches!(open_timeout(), Err(DeviceError::Timeout)));
+/// assert_eq!(retries(), 3);
+/// ```
+///
+/// ```
+/// use handle_error::handle_error;
+///
+/// fn parse_all(items: &[&str]) -> Vec<u32> {
+///     let mut values = vec![];
+///     for i in items {
+///         let v = handle_error!(i.parse(), continue, "Skipping invalid item {}", i);
+///         values.push(v);
+///     }
+///     values
+/// }
+///
+/// fn parse_until_invalid(items: &[&str]) -> Vec<u32> {
+///     let mut values = vec![];
+///     for i in items {
+///         let v = handle_error!(i.parse(), break, "Stopping at invalid item {}", i);
+///         values.push(v);
+///     }
+///     values
+/// }
+///
+/// fn parse_rows(rows: &[&[&str]]) -> Vec<u32> {
+///     let mut totals = vec![];
+///     'rows: for row in rows {
+///         let mut total = 0;
+///         for i in row.iter() {
+///             total += handle_error!(i.parse::<u32>(), continue 'rows, "Skipping row with {}", i);
+///         }
+///         totals.push(total);
+///     }
+///     totals
+/// }
+///
+/// assert_eq!(parse_all(&["1", "x", "3"]), vec![1, 3]);
+/// assert_eq!(parse_until_invalid(&["1", "x", "3"]), vec![1]);
+/// assert_eq!(parse_rows(&[&["1", "2"], &["3", "x"], &["5"]]), vec![3, 5]);
+/// ```
 #[macro_export]
 macro_rules! handle_error {
-    ($call:expr, $msg:expr, $($params:tt)*) => (
-        match $call {
+    (@opts $level:ident $extra:tt $map:tt $catch:tt $fallback:tt $call:expr; map = $m:expr, $($rest:tt)+) => (
+        $crate::handle_error!(@opts $level $extra [$m] $catch $fallback $call; $($rest)+)
+    );
+    (@opts $level:ident $extra:tt $map:tt $catch:tt $fallback:tt $call:expr; display, $($rest:tt)+) => (
+        $crate::handle_error!(@opts $level [error = display] $map $catch $fallback $call; $($rest)+)
+    );
+    (@opts $level:ident $extra:tt $map:tt $catch:tt $fallback:tt $call:expr; debug, $($rest:tt)+) => (
+        $crate::handle_error!(@opts $level [error = debug] $map $catch $fallback $call; $($rest)+)
+    );
+    (@opts $level:ident $extra:tt $map:tt $catch:tt $fallback:tt $call:expr; chain, $($rest:tt)+) => (
+        $crate::handle_error!(@opts $level [error = chain] $map $catch $fallback $call; $($rest)+)
+    );
+    (@opts $level:ident $extra:tt $map:tt $catch:tt $fallback:tt $call:expr; catch_unwind, $($rest:tt)+) => (
+        $crate::handle_error!(@opts $level $extra $map [catch_unwind] $fallback $call; $($rest)+)
+    );
+    (@opts $level:ident $extra:tt $map:tt $catch:tt $fallback:tt $call:expr; default = $default:expr, $($rest:tt)+) => (
+        $crate::handle_error!(@opts $level $extra $map $catch [$default] $call; $($rest)+)
+    );
+    (@opts $level:ident $extra:tt $map:tt $catch:tt $fallback:tt $call:expr; or_else = $f:expr, $($rest:tt)+) => (
+        $crate::handle_error!(@opts $level $extra $map $catch [($f)()] $call; $($rest)+)
+    );
+    (@opts $level:ident $extra:tt $map:tt $catch:tt $fallback:tt $call:expr; continue $($label:lifetime)?, $($rest:tt)+) => (
+        $crate::handle_error!(@opts $level $extra $map $catch [continue $($label)?] $call; $($rest)+)
+    );
+    (@opts $level:ident $extra:tt $map:tt $catch:tt $fallback:tt $call:expr; break $($label:lifetime)?, $($rest:tt)+) => (
+        $crate::handle_error!(@opts $level $extra $map $catch [break $($label)?] $call; $($rest)+)
+    );
+    (@opts $level:ident $extra:tt [$($map:expr)?] $catch:tt [] $call:expr; $($params:tt)+) => (
+        match $crate::handle_error!(@call $catch $call) {
             Ok(v) => v,
             Err(e) => {
-                error!($msg, $($params)*);
-                return Err(e).into();
+                $crate::__site_stats!(handle_error).failure();
+                $crate::__log_error!($level, e, $call, $extra, $($params)+);
+                $( let e = ($map)(e); )?
+                return Err(::core::convert::From::from(e));
+            },
+        }
+    );
+    (@opts $level:ident $extra:tt [] $catch:tt [$fallback:expr] $call:expr; $($params:tt)+) => (
+        match $crate::handle_error!(@call $catch $call) {
+            Ok(v) => v,
+            Err(e) => {
+                $crate::__site_stats!(handle_error).failure();
+                $crate::__log_error!($level, e, $call, $extra, $($params)+);
+                $fallback
+            },
+        }
+    );
+    (@call [] $call:expr) => ($call);
+    (@call [catch_unwind] $call:expr) => (
+        $crate::panic::catch_unwind($crate::__here!(), || $call)
+    );
+    (level = $level:ident, $call:expr => $ctor:expr, $($rest:tt)+) => (
+        $crate::handle_error!(@opts $level [] [$ctor] [] [] $call; $($rest)+)
+    );
+    (level = $level:ident, $call:expr, $($rest:tt)+) => (
+        $crate::handle_error!(@opts $level [] [] [] [] $call; $($rest)+)
+    );
+    ($call:expr => $ctor:expr, $($rest:tt)+) => (
+        $crate::handle_error!(@opts default [] [$ctor] [] [] $call; $($rest)+)
+    );
+    ($call:expr, $($rest:tt)+) => (
+        $crate::handle_error!(@opts default [] [] [] [] $call; $($rest)+)
+    );
+}
+
+/// Log at `warn` level and propagate the error result from a given expression,
+/// see `handle_error!`
+#[macro_export]
+macro_rules! handle_warn {
+    ($($args:tt)+) => (
+        $crate::handle_error!(level = warn, $($args)+)
+    );
+}
+
+/// Log at `info` level and propagate the error result from a given expression,
+/// see `handle_error!`
+#[macro_export]
+macro_rules! handle_info {
+    ($($args:tt)+) => (
+        $crate::handle_error!(level = info, $($args)+)
+    );
+}
+
+/// Log at `debug` level and propagate the error result from a given expression,
+/// see `handle_error!`
+#[macro_export]
+macro_rules! handle_debug {
+    ($($args:tt)+) => (
+        $crate::handle_error!(level = debug, $($args)+)
+    );
+}
+
+/// Log and propagate a `None` from a given `Option` expression
+///
+/// This logs the provided message (as for `handle_error!`) and exits the function scope
+/// on `None`, returning `None` or, where `err = ...` is provided, `Err` with the error
+/// converted using `From`. The unpacked `Some(value)` is returned on success.
+///
+/// ```
+/// use std::collections::HashMap;
+/// use handle_error::handle_none;
+///
+/// fn lookup(m: &HashMap<&str, u32>, k: &str) -> Option<u32> {
+///     let v = handle_none!(m.get(k), "No entry for {}", k);
+///     Some(*v + 1)
+/// }
+///
+/// fn lookup_or_err(m: &HashMap<&str, u32>, k: &str) -> Result<u32, String> {
+///     let v = handle_none!(m.get(k), err = format!("missing {}", k), "No entry for {}", k);
+///     Ok(*v + 1)
+/// }
+///
+/// let m: HashMap<_, _> = vec![("a", 1)].into_iter().collect();
+/// assert_eq!(lookup(&m, "a"), Some(2));
+/// assert_eq!(lookup(&m, "b"), None);
+/// assert_eq!(lookup_or_err(&m, "b"), Err("missing b".to_string()));
+/// ```
+#[macro_export]
+macro_rules! handle_none {
+    (@handle $level:ident $call:expr, err = $err:expr, $($params:tt)+) => (
+        match $call {
+            Some(v) => v,
+            None => {
+                $crate::__site_stats!(handle_none).failure();
+                $crate::__log_error!($level, (), $call, [no_error], $($params)+);
+                return Err(::core::convert::From::from($err));
+            },
+        }
+    );
+    (@handle $level:ident $call:expr, $($params:tt)+) => (
+        match $call {
+            Some(v) => v,
+            None => {
+                $crate::__site_stats!(handle_none).failure();
+                $crate::__log_error!($level, (), $call, [no_error], $($params)+);
+                return None;
             },
-        };
+        }
     );
-    ($call:expr, $msg:expr) => (
+    (level = $level:ident, $call:expr, $($rest:tt)+) => (
+        $crate::handle_none!(@handle $level $call, $($rest)+)
+    );
+    ($call:expr, $($rest:tt)+) => (
+        $crate::handle_none!(@handle default $call, $($rest)+)
+    );
+}
+
+/// Log and propagate the error result from a given expression, wrapping the error
+/// with the message and location as a [`context::ContextError`]
+///
+/// This behaves as `handle_error!` (including `display`, `debug` and `chain` options),
+/// with the returned error converted from the `ContextError` using `From` so the context
+/// is available to callers.
+///
+/// ```
+/// use handle_error::{context_error, context::ContextError};
+///
+/// fn open(path: &str) -> Result<std::fs::File, ContextError<std::io::Error>> {
+///     let f = context_error!(std::fs::File::open(path), "Failed to open {}", path);
+///     Ok(f)
+/// }
+///
+/// let e = open("/does/not/exist").unwrap_err();
+/// assert_eq!(e.message(), "Failed to open /does/not/exist");
+/// assert_eq!(e.location().file, file!());
+/// assert_eq!(e.get_ref().kind(), std::io::ErrorKind::NotFound);
+/// ```
+#[macro_export]
+macro_rules! context_error {
+    (@handle $level:ident $extra:tt $call:expr, $($params:tt)+) => (
         match $call {
             Ok(v) => v,
             Err(e) => {
-                error!($msg);
-                return Err(e).into();
+                $crate::__site_stats!(context_error).failure();
+                let message = $crate::__log_message!($level, e, $call, $extra, $($params)+);
+                let e = $crate::context::ContextError::new(message, $crate::__here!(), e);
+                return Err(::core::convert::From::from(e));
             },
-        };
+        }
+    );
+    (@mode $level:ident $call:expr, display, $($params:tt)+) => (
+        $crate::context_error!(@handle $level [error = display] $call, $($params)+)
+    );
+    (@mode $level:ident $call:expr, debug, $($params:tt)+) => (
+        $crate::context_error!(@handle $level [error = debug] $call, $($params)+)
+    );
+    (@mode $level:ident $call:expr, chain, $($params:tt)+) => (
+        $crate::context_error!(@handle $level [error = chain] $call, $($params)+)
+    );
+    (@mode $level:ident $call:expr, $($params:tt)+) => (
+        $crate::context_error!(@handle $level [] $call, $($params)+)
+    );
+    (level = $level:ident, $call:expr, $($rest:tt)+) => (
+        $crate::context_error!(@mode $level $call, $($rest)+)
+    );
+    ($call:expr, $($rest:tt)+) => (
+        $crate::context_error!(@mode default $call, $($rest)+)
     );
 }
 
-/// Retry a provided fallible function N times
+/// Retry a provided fallible expression up to N times
+///
+/// This will optionally log a message (with `; key = value` fields as for `handle_error!`),
+/// and returns the final error if all attempts fail
+///
+/// The expression is evaluated once and then retried up to N times on failure, making up to
+/// N + 1 attempts. This may also be written as `retries = N`, or the total number of attempts
+/// provided with `attempts = N`. The expression may be written as `|attempt| ...` to receive
+/// the current attempt number (starting at 1), for example to use a secondary endpoint on
+/// later attempts.
+///
+/// A `backoff` (see [`backoff::Backoff`], or a `Duration` for a constant delay) may be
+/// provided to sleep between attempts, using [`clock::SystemClock`] or the provided `clock`.
+/// Retries may be limited to errors matching a predicate with `when = |e: &E| ...`, or to
+/// errors implementing [`retry::Transient`] with `transient`. See [`retry::RetryPolicy`]
+/// for details.
+///
+/// A `deadline` (as a `Duration` from the first attempt or an `Instant`) stops retrying once
+/// the next attempt would start after it. With the `all` option the errors from every attempt
+/// are returned as a [`retry::RetryError`], including whether the attempt limit or deadline
+/// was reached.
+///
+/// Where a message is provided each failed attempt that will be retried is logged at `warn`
+/// level (or the default level where that is less severe, see
+/// [Logging levels](crate#logging-levels)) with the attempt number and error, and
+/// `on_retry = |attempt, e| ...` may be used to observe failed attempts (see
+/// [`retry::RetryPolicy::on_retry`]). The final failure is logged at the default level with
+/// the [`retry::StopReason`] retrying stopped for and the error.
+///
+/// Attempts may be routed through a [`breaker::CircuitBreaker`] with `breaker = &breaker`,
+/// failing fast while the breaker is open and stopping retries once it opens.
+///
+/// With the `catch_unwind` option panics in the expression are caught and retried as
+/// errors, see [`panic`].
+///
+/// This is shorthand for running the expression under a [`retry::RetryPolicy`], and as the
+/// expression is evaluated within a closure `?` and `return` apply to the current attempt
+/// rather than the enclosing function. Where this is surprising, or the policy is configured
+/// at runtime, use [`retry::retry`] directly.
+///
+/// ```
+/// use std::io::{Error, ErrorKind};
+/// use std::time::Duration;
+/// use handle_error::{retry_error, backoff::Backoff, clock::ManualClock};
 ///
-/// This will optionally log a message, and returns the final error if all attempts fail
+/// let clock = ManualClock::new();
+/// let backoff = Backoff::exponential(Duration::from_secs(1), Duration::from_secs(60));
+///
+/// let r: Result<(), Error> = retry_error!(3, backoff = backoff, clock = &clock,
+///     Err(ErrorKind::TimedOut.into()), "Failed to do something");
+///
+/// assert_eq!(r.unwrap_err().kind(), ErrorKind::TimedOut);
+/// assert_eq!(clock.elapsed(), Duration::from_secs(1 + 2 + 4));
+///
+/// let r: Result<(), Error> = retry_error!(3, transient,
+///     Err(ErrorKind::PermissionDenied.into()), "Permission denied");
+/// assert!(r.is_err());
+///
+/// let r: Result<(), _> = retry_error!(2, all, Err::<(), Error>(ErrorKind::TimedOut.into()), "Failed");
+/// assert_eq!(r.unwrap_err().attempts().len(), 3);
+///
+/// let r: Result<u32, Error> = retry_error!(attempts = 3, |attempt| match attempt {
+///     1 => Err(ErrorKind::TimedOut.into()),
+///     n => Ok(n),
+/// }, "Failed to connect");
+/// assert_eq!(r.unwrap(), 2);
+/// ```
+#[cfg(feature = "std")]
 #[macro_export]
 macro_rules! retry_error {
-    ($retries:expr, $fallible:expr, $($params:tt)*) => (
-        (|| {
-            let mut i = 0;
-            loop {
-                match $fallible {
-                    Ok(v) => break Ok(v),
-                    Err(e) if i < $retries => {
-                        i += 1;
-                    },
-                    Err(e) => {
-                        error!($($params)*);
-                        break Err(e)
-                    },
-                }
-            }
-        })()
-    );
-    ($retries:expr, $fallible:expr) => (
-        (|| {
-            let mut i = 0;
-            loop {
-                match $fallible {
-                    Ok(v) => break Ok(v),
-                    Err(e) if i < $retries => {
-                        i += 1;
-                    },
+    (@opts $head:tt [$($opts:tt)*] backoff = $backoff:expr, $($rest:tt)+) => (
+        $crate::retry_error!(@opts $head [$($opts)* .backoff($backoff)] $($rest)+)
+    );
+    (@opts $head:tt [$($opts:tt)*] clock = $clock:expr, $($rest:tt)+) => (
+        $crate::retry_error!(@opts $head [$($opts)* .clock($clock)] $($rest)+)
+    );
+    (@opts $head:tt [$($opts:tt)*] deadline = $deadline:expr, $($rest:tt)+) => (
+        $crate::retry_error!(@opts $head [$($opts)* .deadline($deadline)] $($rest)+)
+    );
+    (@opts $head:tt [$($opts:tt)*] when = $predicate:expr, $($rest:tt)+) => (
+        $crate::retry_error!(@opts $head [$($opts)* .when($predicate)] $($rest)+)
+    );
+    (@opts $head:tt [$($opts:tt)*] transient, $($rest:tt)+) => (
+        $crate::retry_error!(@opts $head [$($opts)* .transient()] $($rest)+)
+    );
+    (@opts [$retries:expr; $collect:expr; $($hook:expr)?; $($breaker:expr)?; $($catch:ident)?] $opts:tt all, $($rest:tt)+) => (
+        $crate::retry_error!(@opts [$retries; $crate::retry::RetryError::new(); $($hook)?; $($breaker)?; $($catch)?] $opts $($rest)+)
+    );
+    (@opts [$retries:expr; $collect:expr; $($hook:expr)?; $($breaker:expr)?; $($catch:ident)?] $opts:tt on_retry = $h:expr, $($rest:tt)+) => (
+        $crate::retry_error!(@opts [$retries; $collect; $h; $($breaker)?; $($catch)?] $opts $($rest)+)
+    );
+    (@opts [$retries:expr; $collect:expr; $($hook:expr)?; $($breaker:expr)?; $($catch:ident)?] $opts:tt breaker = $b:expr, $($rest:tt)+) => (
+        $crate::retry_error!(@opts [$retries; $collect; $($hook)?; $b; $($catch)?] $opts $($rest)+)
+    );
+    (@opts [$retries:expr; $collect:expr; $($hook:expr)?; $($breaker:expr)?; $($catch:ident)?] $opts:tt catch_unwind, $($rest:tt)+) => (
+        $crate::retry_error!(@opts [$retries; $collect; $($hook)?; $($breaker)?; catch_unwind] $opts $($rest)+)
+    );
+    (@opts $head:tt $opts:tt |$attempt:ident| $fallible:expr $(, $($params:tt)*)?) => (
+        $crate::retry_error!(@run $head $opts [$attempt] $fallible $(, $($params)*)?)
+    );
+    (@opts $head:tt $opts:tt $fallible:expr $(, $($params:tt)*)?) => (
+        $crate::retry_error!(@run $head $opts [_attempt] $fallible $(, $($params)*)?)
+    );
+    (@run [$retries:expr; $collect:expr; $($hook:expr)?; ; $($catch:ident)?] [$($opts:tt)*] $($rest:tt)+) => ({
+        let policy = $crate::retry::RetryPolicy::new($retries)$($opts)* $(.on_retry($hook))?;
+        $crate::retry_error!(@loop policy [] [$collect] [$($catch)?] $($rest)+)
+    });
+    (@run [$retries:expr; $collect:expr; $($hook:expr)?; $breaker:expr; $($catch:ident)?] [$($opts:tt)*] $($rest:tt)+) => ({
+        let breaker = $breaker;
+        let policy = $crate::retry::RetryPolicy::new($retries)$($opts)* $(.on_retry($hook))?.breaker(breaker);
+        $crate::retry_error!(@loop policy [breaker] [$collect] [$($catch)?] $($rest)+)
+    });
+    (@loop $policy:ident $breaker:tt $collect:tt $catch:tt [$attempt:ident] $fallible:expr) => (
+        $crate::retry_error!(@loop $policy $breaker $collect $catch [$attempt] $fallible,)
+    );
+    (@loop $policy:ident $breaker:tt [$collect:expr] $catch:tt [$attempt:ident] $fallible:expr, $($($params:tt)+)?) => ({
+        let mut state = $policy.start($collect).stats($crate::__site_stats!(retry_error));
+        loop {
+            let $attempt = state.attempt();
+            match $crate::retry_error!(@attempt $breaker $catch $fallible) {
+                Ok(v) => {
+                    state.on_success();
+                    break Ok(v)
+                },
+                Err(e) => match $crate::retry_error!(@failure state, e, $fallible $(, $($params)+)?) {
+                    Ok(d) => $policy.sleep(d),
                     Err(e) => break Err(e),
-                }
+                },
+            }
+        }
+    });
+    (@attempt [] $catch:tt $fallible:expr) => (
+        (|| $crate::retry_error!(@call $catch $fallible))()
+    );
+    (@attempt [$breaker:ident] $catch:tt $fallible:expr) => (
+        $breaker.call(|| $crate::retry_error!(@call $catch $fallible))
+    );
+    (@failure $state:ident, $e:ident, $fallible:expr) => (
+        $state.on_failure($e)
+    );
+    (@failure $state:ident, $e:ident, $fallible:expr, $($params:tt)+) => ({
+        let attempt = $state.attempt();
+        $state.on_failure_with($e, |e, reason| match reason {
+            None => $crate::__log_error!(attempt, e, $fallible, [attempt = attempt], $($params)+),
+            Some(reason) => $crate::__log_error!(default, e, $fallible, [reason = reason], $($params)+),
+        })
+    });
+    (@call [] $fallible:expr) => ($fallible);
+    (@call [catch_unwind] $fallible:expr) => (
+        $crate::panic::catch_unwind($crate::__here!(), || $fallible)
+    );
+    (attempts = $attempts:expr, $($rest:tt)+) => (
+        $crate::retry_error!(@opts [0; $crate::retry::Last;;;] [.attempts($attempts)] $($rest)+)
+    );
+    (retries = $retries:expr, $($rest:tt)+) => (
+        $crate::retry_error!(@opts [$retries; $crate::retry::Last;;;] [] $($rest)+)
+    );
+    ($retries:expr, $($rest:tt)+) => (
+        $crate::retry_error!(@opts [$retries; $crate::retry::Last;;;] [] $($rest)+)
+    );
+}
+
+/// Retry a provided fallible expression up to N times, without the `std` feature
+///
+/// With no clock available attempts are retried immediately, and only the `retries` or
+/// `attempts` limit, `|attempt| ...` expressions and logging are supported.
+#[cfg(not(feature = "std"))]
+#[macro_export]
+macro_rules! retry_error {
+    (@opts $retries:tt |$attempt:ident| $fallible:expr $(, $($params:tt)*)?) => (
+        $crate::retry_error!(@run $retries [$attempt] $fallible $(, $($params)*)?)
+    );
+    (@opts $retries:tt $fallible:expr $(, $($params:tt)*)?) => (
+        $crate::retry_error!(@run $retries [_attempt] $fallible $(, $($params)*)?)
+    );
+    (@run $retries:tt $attempt:tt $fallible:expr,) => (
+        $crate::retry_error!(@run $retries $attempt $fallible)
+    );
+    (@run [$retries:expr] [$attempt:ident] $fallible:expr $(, $($params:tt)+)?) => ({
+        let retries: u32 = $retries;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let $attempt: u32 = attempt;
+            match (|| $fallible)() {
+                Ok(v) => break Ok(v),
+                Err(e) if attempt <= retries => {
+                    $( $crate::__log_error!(attempt, e, $fallible, [attempt = attempt], $($params)+); )?
+                },
+                Err(e) => {
+                    $( $crate::__log_error!(default, e, $fallible, [], $($params)+); )?
+                    break Err(e)
+                },
             }
-        })()
+        }
+    });
+    (attempts = $attempts:expr, $($rest:tt)+) => (
+        $crate::retry_error!(@opts [u32::saturating_sub($attempts, 1)] $($rest)+)
+    );
+    (retries = $retries:expr, $($rest:tt)+) => (
+        $crate::retry_error!(@opts [$retries] $($rest)+)
+    );
+    ($retries:expr, $($rest:tt)+) => (
+        $crate::retry_error!(@opts [$retries] $($rest)+)
     );
 }
 
+/// Retry a provided asynchronous fallible expression up to N times
+///
+/// This evaluates and awaits the provided future-producing expression on each attempt,
+/// so must be used in an async context. A `backoff` (see [`backoff::Backoff`], or a `Duration`
+/// for a constant delay) may be provided to sleep between attempts, using either the provided
+/// `sleep` implementation (see [`retry::AsyncSleep`]) or [`retry::DefaultSleep`] where the
+/// `tokio` or `async-std` features are enabled. The `when`, `transient`, `deadline`, `all` and
+/// `on_retry` options, `retries`/`attempts` limits, `|attempt| ...` expressions and
+/// per-attempt logging behave as for `retry_error!`.
+///
+/// Deadlines are timed using [`clock::SystemClock`] or the provided `clock`, which is not
+/// used to sleep, so a [`clock::ManualClock`] should be advanced by the provided `sleep`.
+///
+/// As with `retry_error!` this will optionally log a message, and returns the final
+/// error if all attempts fail.
+///
+/// ```
+/// use std::cell::Cell;
+/// use std::time::Duration;
+/// use handle_error::retry_error_async;
+///
+/// async fn connect(attempts: &Cell<u32>) -> Result<u32, std::io::Error> {
+///     attempts.set(attempts.get() + 1);
+///     match attempts.get() {
+///         n if n < 3 => Err(std::io::ErrorKind::TimedOut.into()),
+///         n => Ok(n),
+///     }
+/// }
+///
+/// async fn example() -> Result<u32, std::io::Error> {
+///     let attempts = Cell::new(0);
+///     let sleep = |_d: Duration| async {};
+///
+///     let v = retry_error_async!(3, backoff = Duration::from_millis(10), sleep = sleep,
+///         connect(&attempts), "Failed to connect")?;
+///     Ok(v)
+/// }
+///
+/// assert_eq!(futures_executor::block_on(example()).unwrap(), 3);
+/// ```
+///
+/// With a [`clock::ManualClock`] deadlines may be tested without waiting:
+///
+/// ```
+/// use std::time::Duration;
+/// use handle_error::{retry_error_async, clock::ManualClock, retry::StopReason};
+///
+/// let clock = ManualClock::new();
+/// let sleep = |d: Duration| {
+///     clock.advance(d);
+///     async {}
+/// };
+///
+/// let r: Result<(), _> = futures_executor::block_on(async {
+///     retry_error_async!(10, all, backoff = Duration::from_secs(1), sleep = sleep, clock = &clock,
+///         deadline = Duration::from_millis(2500), async { Err::<(), _>("nope") })
+/// });
+///
+/// let e = r.unwrap_err();
+/// assert_eq!(e.attempts().len(), 3);
+/// assert_eq!(e.reason(), StopReason::Deadline);
+/// ```
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! retry_error_async {
+    (@opts $head:tt [] [$($opts:tt)*] backoff = $backoff:expr, $($rest:tt)+) => (
+        $crate::retry_error_async!(@opts $head [default] [$($opts)* .backoff($backoff)] $($rest)+)
+    );
+    (@opts $head:tt $sleep:tt [$($opts:tt)*] backoff = $backoff:expr, $($rest:tt)+) => (
+        $crate::retry_error_async!(@opts $head $sleep [$($opts)* .backoff($backoff)] $($rest)+)
+    );
+    (@opts $head:tt $sleep:tt $opts:tt sleep = $s:expr, $($rest:tt)+) => (
+        $crate::retry_error_async!(@opts $head [= $s] $opts $($rest)+)
+    );
+    (@opts $head:tt $sleep:tt [$($opts:tt)*] clock = $clock:expr, $($rest:tt)+) => (
+        $crate::retry_error_async!(@opts $head $sleep [$($opts)* .clock($clock)] $($rest)+)
+    );
+    (@opts $head:tt $sleep:tt [$($opts:tt)*] deadline = $deadline:expr, $($rest:tt)+) => (
+        $crate::retry_error_async!(@opts $head $sleep [$($opts)* .deadline($deadline)] $($rest)+)
+    );
+    (@opts $head:tt $sleep:tt [$($opts:tt)*] when = $predicate:expr, $($rest:tt)+) => (
+        $crate::retry_error_async!(@opts $head $sleep [$($opts)* .when($predicate)] $($rest)+)
+    );
+    (@opts $head:tt $sleep:tt [$($opts:tt)*] transient, $($rest:tt)+) => (
+        $crate::retry_error_async!(@opts $head $sleep [$($opts)* .transient()] $($rest)+)
+    );
+    (@opts $head:tt $sleep:tt [$($opts:tt)*] on_retry = $hook:expr, $($rest:tt)+) => (
+        $crate::retry_error_async!(@opts $head $sleep [$($opts)* .on_retry($hook)] $($rest)+)
+    );
+    (@opts [$retries:expr; $collect:expr] $sleep:tt $opts:tt all, $($rest:tt)+) => (
+        $crate::retry_error_async!(@opts [$retries; $crate::retry::RetryError::new()] $sleep $opts $($rest)+)
+    );
+    (@opts $head:tt [] $opts:tt $($rest:tt)+) => (
+        $crate::retry_error_async!(@run $head [$crate::retry::NoSleep] $opts $($rest)+)
+    );
+    (@opts $head:tt [default] $opts:tt $($rest:tt)+) => (
+        $crate::retry_error_async!(@run $head [$crate::retry::DefaultSleep::default()] $opts $($rest)+)
+    );
+    (@opts $head:tt [= $sleep:expr] $opts:tt $($rest:tt)+) => (
+        $crate::retry_error_async!(@run $head [$sleep] $opts $($rest)+)
+    );
+    (@run $head:tt $sleep:tt $opts:tt |$attempt:ident| $fallible:expr $(, $($params:tt)+)?) => (
+        $crate::retry_error_async!(@loop $head $sleep $opts [$attempt] $fallible $(, $($params)+)?)
+    );
+    (@run $head:tt $sleep:tt $opts:tt $fallible:expr $(, $($params:tt)+)?) => (
+        $crate::retry_error_async!(@loop $head $sleep $opts [_attempt] $fallible $(, $($params)+)?)
+    );
+    (@loop [$retries:expr; $collect:expr] [$sleep:expr] [$($opts:tt)*] [$attempt:ident] $fallible:expr $(, $($params:tt)+)?) => ({
+        let policy = $crate::retry::RetryPolicy::new($retries)$($opts)*;
+        let sleep = $sleep;
+        let mut state = policy.start($collect).stats($crate::__site_stats!(retry_error_async));
+        loop {
+            let $attempt = state.attempt();
+            match $fallible.await {
+                Ok(v) => {
+                    state.on_success();
+                    break Ok(v)
+                },
+                Err(e) => match $crate::retry_error!(@failure state, e, $fallible $(, $($params)+)?) {
+                    Ok(d) => $crate::retry::AsyncSleep::sleep(&sleep, d).await,
+                    Err(e) => break Err(e),
+                },
+            }
+        }
+    });
+    (attempts = $attempts:expr, $($rest:tt)+) => (
+        $crate::retry_error_async!(@opts [0; $crate::retry::Last] [] [.attempts($attempts)] $($rest)+)
+    );
+    (retries = $retries:expr, $($rest:tt)+) => (
+        $crate::retry_error_async!(@opts [$retries; $crate::retry::Last] [] [] $($rest)+)
+    );
+    ($retries:expr, $($rest:tt)+) => (
+        $crate::retry_error_async!(@opts [$retries; $crate::retry::Last] [] [] $($rest)+)
+    );
+}
@@ -0,0 +1,142 @@
+//! Rate limiting of repeated log messages
+//!
+//! Each macro call site tracks the messages it has logged, so that a site failing in
+//! a hot loop logs the first `burst` messages and then at most one per `interval`.
+//! When logging resumes following suppressed messages, a summary of the number of
+//! suppressed messages is logged first. Sites are independent, so unrelated sites do
+//! not interfere with each other.
+//!
+//! Rate limiting is disabled by default and is enabled for all sites with
+//! [`set_rate_limit`].
+//!
+//! ```
+//! use std::time::Duration;
+//! use handle_error::limit::{set_rate_limit, RateLimit};
+//!
+//! set_rate_limit(Some(RateLimit::new(5, Duration::from_secs(10))));
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static LIMIT: RwLock<RateLimit> = RwLock::new(RateLimit::new(0, Duration::from_secs(0)));
+
+/// Limit on the messages logged by each call site
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Number of messages logged before limiting applies
+    pub burst: u32,
+    /// Minimum interval between messages once the burst is exhausted
+    pub interval: Duration,
+}
+
+impl RateLimit {
+    /// Create a new rate limit, logging `burst` messages and then one per `interval`
+    pub const fn new(burst: u32, interval: Duration) -> Self {
+        Self { burst, interval }
+    }
+}
+
+/// Set the rate limit applied to all call sites, or `None` to disable rate limiting
+pub fn set_rate_limit(limit: Option<RateLimit>) {
+    if let Some(l) = limit {
+        *LIMIT.write().unwrap() = l;
+    }
+    ENABLED.store(limit.is_some(), Ordering::Release);
+}
+
+/// Fetch the rate limit applied to all call sites
+pub fn rate_limit() -> Option<RateLimit> {
+    if !ENABLED.load(Ordering::Acquire) {
+        return None;
+    }
+    Some(*LIMIT.read().unwrap())
+}
+
+/// Rate limiting state for a single call site
+///
+/// This is created as a `static` by each macro call site, and may be used directly
+/// to limit other messages.
+///
+/// ```
+/// use std::time::{Duration, Instant};
+/// use handle_error::limit::{RateLimit, Site};
+///
+/// static SITE: Site = Site::new();
+///
+/// let limit = RateLimit::new(2, Duration::from_secs(10));
+/// let now = Instant::now();
+///
+/// assert_eq!(SITE.check_at(&limit, now), Some(0));
+/// assert_eq!(SITE.check_at(&limit, now), Some(0));
+/// assert_eq!(SITE.check_at(&limit, now), None);
+/// assert_eq!(SITE.check_at(&limit, now), None);
+///
+/// // Logging resumes once the interval has elapsed, reporting the suppressed messages
+/// assert_eq!(SITE.check_at(&limit, now + Duration::from_secs(10)), Some(2));
+/// ```
+#[derive(Debug)]
+pub struct Site {
+    state: Mutex<SiteState>,
+}
+
+#[derive(Debug)]
+struct SiteState {
+    logged: u32,
+    last: Option<Instant>,
+    suppressed: u64,
+}
+
+impl Site {
+    /// Create a new call site
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(SiteState {
+                logged: 0,
+                last: None,
+                suppressed: 0,
+            }),
+        }
+    }
+
+    /// Check whether a message should be logged under the configured rate limit (see
+    /// [`set_rate_limit`]), returning the number of messages suppressed since the last
+    /// logged message or `None` where this message should be suppressed
+    pub fn check(&self) -> Option<u64> {
+        match rate_limit() {
+            Some(l) => self.check_at(&l, Instant::now()),
+            None => Some(0),
+        }
+    }
+
+    /// Check whether a message should be logged at the provided instant under the
+    /// provided rate limit, see [`Site::check`]
+    pub fn check_at(&self, limit: &RateLimit, now: Instant) -> Option<u64> {
+        let mut s = self.state.lock().unwrap();
+
+        let expired = s.last.map(|l| now.saturating_duration_since(l) >= limit.interval);
+
+        // Start a new burst where nothing has been suppressed for an interval
+        if s.suppressed == 0 && expired == Some(true) {
+            s.logged = 0;
+        }
+
+        if s.logged < limit.burst || expired.unwrap_or(true) {
+            s.logged = s.logged.saturating_add(1);
+            s.last = Some(now);
+            return Some(std::mem::take(&mut s.suppressed));
+        }
+
+        s.suppressed += 1;
+        None
+    }
+}
+
+impl Default for Site {
+    fn default() -> Self {
+        Self::new()
+    }
+}
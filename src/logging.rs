@@ -0,0 +1,455 @@
+//! Logging backend selection
+//!
+//! All logging emitted by this crate goes through the hidden `__log!` macro, which
+//! forwards to whichever backend has been selected with cargo features. Where more than
+//! one backend is enabled the first of `tracing`, `log`, `defmt` and `eprintln` is used,
+//! and with no backend enabled messages are discarded.
+
+/// Emit a log message at the provided level via the `tracing` backend
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log {
+    ($level:ident, $($arg:tt)+) => (
+        $crate::__private::tracing::$level!($($arg)+)
+    );
+}
+
+/// Emit a log message at the provided level via the `log` backend
+#[cfg(all(feature = "log", not(feature = "tracing")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log {
+    ($level:ident, $($arg:tt)+) => (
+        $crate::__private::log::$level!($($arg)+)
+    );
+}
+
+/// Emit a log message at the provided level via the `defmt` backend
+#[cfg(all(feature = "defmt", not(any(feature = "tracing", feature = "log"))))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log {
+    ($level:ident, $($arg:tt)+) => (
+        $crate::__private::defmt::$level!($($arg)+)
+    );
+}
+
+/// Emit a log message at the provided level to stderr
+#[cfg(all(feature = "eprintln", not(any(feature = "tracing", feature = "log", feature = "defmt"))))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log {
+    ($level:ident, $($arg:tt)+) => (
+        eprintln!("[{}] {}", stringify!($level), format_args!($($arg)+))
+    );
+}
+
+/// Discard log messages where no backend is enabled
+#[cfg(not(any(feature = "tracing", feature = "log", feature = "defmt", feature = "eprintln")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log {
+    ($level:ident, $($arg:tt)+) => (
+        { let _ = format_args!($($arg)+); }
+    );
+}
+
+/// Emit a log message for an error returned by a call site, splitting the
+/// message arguments from any `; key = value` fields that follow them
+///
+/// Messages are rate limited per call site with the `std` feature, see [`limit`](crate::limit).
+///
+/// The level may be `default`, or `attempt` for failed retry attempts (see `__attempt_level!`).
+///
+/// The bracketed slot selects formatting of the error with `[error = display|debug|chain]`,
+/// is used to log failed retry attempts with `[attempt = n]` and the final failure with
+/// `[reason = r]`, or `[no_error]` where there is no error value to record.
+///
+/// Fields are passed to the backends as `(key "={:?}" [?] value)`, with the format used to
+/// append them to messages and any `%` or `?` marker to record them on `tracing` events.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_error {
+    (@munch $out:tt $level:ident, $err:expr, $call:expr, $extra:tt, $msg:tt ; $($fields:tt)*) => (
+        $crate::__log_error!(@fields $out $level, $err, $call, $extra, $msg [] $($fields)*)
+    );
+    (@munch $out:tt $level:ident, $err:expr, $call:expr, $extra:tt, [$($msg:tt)*] $next:tt $($rest:tt)*) => (
+        $crate::__log_error!(@munch $out $level, $err, $call, $extra, [$($msg)* $next] $($rest)*)
+    );
+    (@munch $out:tt $level:ident, $err:expr, $call:expr, $extra:tt, [$($msg:tt)*]) => (
+        $crate::__log_error!(@out $out $level, $err, $call, [$($msg)*], [], $extra)
+    );
+    (@fields $out:tt $level:ident, $err:expr, $call:expr, $extra:tt, $msg:tt [$($fields:tt)*] $key:ident = % $value:expr $(, $($rest:tt)*)?) => (
+        $crate::__log_error!(@fields $out $level, $err, $call, $extra, $msg [$($fields)* ($key "={}" [%] $value)] $($($rest)*)?)
+    );
+    (@fields $out:tt $level:ident, $err:expr, $call:expr, $extra:tt, $msg:tt [$($fields:tt)*] $key:ident = ? $value:expr $(, $($rest:tt)*)?) => (
+        $crate::__log_error!(@fields $out $level, $err, $call, $extra, $msg [$($fields)* ($key "={:?}" [?] $value)] $($($rest)*)?)
+    );
+    (@fields $out:tt $level:ident, $err:expr, $call:expr, $extra:tt, $msg:tt [$($fields:tt)*] $key:ident = $value:expr $(, $($rest:tt)*)?) => (
+        $crate::__log_error!(@fields $out $level, $err, $call, $extra, $msg [$($fields)* ($key "={:?}" [] $value)] $($($rest)*)?)
+    );
+    (@fields $out:tt $level:ident, $err:expr, $call:expr, $extra:tt, $msg:tt $fields:tt) => (
+        $crate::__log_error!(@out $out $level, $err, $call, $msg, $fields, $extra)
+    );
+    (@out [log] $($args:tt)*) => (
+        $crate::__log_limited!($($args)*)
+    );
+    (@out [message] $level:ident, $err:expr, $call:expr, [$($msg:tt)*], $fields:tt, $extra:tt) => ({
+        let message = <$crate::context::DefaultMessage as $crate::context::Message>::from_args(format_args!($($msg)*));
+        $crate::__log_limited!($level, $err, $call, ["{}", ::core::convert::AsRef::<str>::as_ref(&message)], $fields, $extra);
+        message
+    });
+    (default, $($rest:tt)*) => (
+        $crate::__with_default_level!(__log_error, $($rest)*)
+    );
+    (attempt, $($rest:tt)*) => (
+        $crate::__with_default_level!(__attempt_level, __log_error, $($rest)*)
+    );
+    ($level:ident, $err:expr, $call:expr, $extra:tt, $($params:tt)*) => (
+        $crate::__log_error!(@munch [log] $level, $err, $call, $extra, [] $($params)*)
+    );
+}
+
+/// Format a [`DefaultMessage`](crate::context::DefaultMessage) from the provided arguments
+/// and log it as for `__log_error!`, evaluating to the message
+///
+/// The message arguments are evaluated once, whether or not the message is logged.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_message {
+    (default, $($rest:tt)*) => (
+        $crate::__with_default_level!(__log_message, $($rest)*)
+    );
+    ($level:ident, $err:expr, $call:expr, $extra:tt, $($params:tt)*) => (
+        $crate::__log_error!(@munch [message] $level, $err, $call, $extra, [] $($params)*)
+    );
+}
+
+/// Emit a log message for an error where permitted by the call site rate limit
+#[cfg(feature = "std")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_limited {
+    ($level:ident, $($args:tt)*) => ({
+        static SITE: $crate::limit::Site = $crate::limit::Site::new();
+        if let Some(suppressed) = SITE.check() {
+            if suppressed > 0 {
+                $crate::__log!($level, "Suppressed {} similar messages", suppressed);
+            }
+            $crate::__log_error_fields!($level, $($args)*)
+        }
+    });
+}
+
+/// Rate limiting requires the `std` feature, so all messages are emitted
+#[cfg(not(feature = "std"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_limited {
+    ($level:ident, $($args:tt)*) => (
+        $crate::__log_error_fields!($level, $($args)*)
+    );
+}
+
+/// Invoke the provided logging macro at the level for failed retry attempts given the
+/// default level, `warn` unless the default is less severe
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __attempt_level {
+    (error, $mac:ident, $($rest:tt)*) => ($crate::$mac!(warn, $($rest)*));
+    ($level:ident, $mac:ident, $($rest:tt)*) => ($crate::$mac!($level, $($rest)*));
+}
+
+/// Invoke the provided logging macro at the default level (`error`, or as set by the
+/// most severe `default-level-*` feature)
+#[cfg(not(any(feature = "default-level-warn", feature = "default-level-info",
+    feature = "default-level-debug", feature = "default-level-trace")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __with_default_level {
+    ($mac:ident, $($rest:tt)*) => ($crate::$mac!(error, $($rest)*));
+}
+
+#[cfg(feature = "default-level-warn")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __with_default_level {
+    ($mac:ident, $($rest:tt)*) => ($crate::$mac!(warn, $($rest)*));
+}
+
+#[cfg(all(feature = "default-level-info", not(feature = "default-level-warn")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __with_default_level {
+    ($mac:ident, $($rest:tt)*) => ($crate::$mac!(info, $($rest)*));
+}
+
+#[cfg(all(feature = "default-level-debug",
+    not(any(feature = "default-level-warn", feature = "default-level-info"))))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __with_default_level {
+    ($mac:ident, $($rest:tt)*) => ($crate::$mac!(debug, $($rest)*));
+}
+
+#[cfg(all(feature = "default-level-trace",
+    not(any(feature = "default-level-warn", feature = "default-level-info", feature = "default-level-debug"))))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __with_default_level {
+    ($mac:ident, $($rest:tt)*) => ($crate::$mac!(trace, $($rest)*));
+}
+
+/// Emit an error event via `tracing`, recording the error, call site expression
+/// and any additional fields
+///
+/// As with other backends the error is only recorded where `display`, `debug` or `chain`
+/// formatting is selected (or for failed retry attempts, using `Debug`), so no additional
+/// bounds are placed on the error type.
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_error_fields {
+    ($level:ident, $err:expr, $call:expr, [$($msg:tt)*], [$(($key:ident $fmt:literal [$($marker:tt)*] $value:expr))*], [$(no_error)?]) => (
+        $crate::__private::tracing::$level!(
+            expr = stringify!($call),
+            column = $crate::__column!(),
+            $($key = $($marker)* $value,)*
+            $($msg)*
+        )
+    );
+    ($level:ident, $err:expr, $call:expr, [$($msg:tt)*], [$(($key:ident $fmt:literal [$($marker:tt)*] $value:expr))*], [error = display]) => (
+        $crate::__private::tracing::$level!(
+            error = %$err,
+            expr = stringify!($call),
+            column = $crate::__column!(),
+            $($key = $($marker)* $value,)*
+            $($msg)*
+        )
+    );
+    ($level:ident, $err:expr, $call:expr, [$($msg:tt)*], [$(($key:ident $fmt:literal [$($marker:tt)*] $value:expr))*], [error = debug]) => (
+        $crate::__private::tracing::$level!(
+            error = ?$err,
+            expr = stringify!($call),
+            column = $crate::__column!(),
+            $($key = $($marker)* $value,)*
+            $($msg)*
+        )
+    );
+    ($level:ident, $err:expr, $call:expr, [$($msg:tt)*], [$(($key:ident $fmt:literal [$($marker:tt)*] $value:expr))*], [error = chain]) => (
+        $crate::__private::tracing::$level!(
+            error = &$err as &(dyn ::core::error::Error + 'static),
+            expr = stringify!($call),
+            column = $crate::__column!(),
+            $($key = $($marker)* $value,)*
+            $($msg)*
+        )
+    );
+    ($level:ident, $err:expr, $call:expr, [$($msg:tt)*], [$(($key:ident $fmt:literal [$($marker:tt)*] $value:expr))*], [attempt = $attempt:expr]) => (
+        $crate::__private::tracing::$level!(
+            error = ?$err,
+            expr = stringify!($call),
+            column = $crate::__column!(),
+            attempt = $attempt,
+            $($key = $($marker)* $value,)*
+            $($msg)*
+        )
+    );
+    ($level:ident, $err:expr, $call:expr, [$($msg:tt)*], [$(($key:ident $fmt:literal [$($marker:tt)*] $value:expr))*], [reason = $reason:expr]) => (
+        $crate::__private::tracing::$level!(
+            error = ?$err,
+            expr = stringify!($call),
+            column = $crate::__column!(),
+            reason = %$reason,
+            $($key = $($marker)* $value,)*
+            $($msg)*
+        )
+    );
+}
+
+/// Emit an error message via `defmt`, fields are not supported and are discarded
+#[cfg(all(feature = "defmt", not(any(feature = "tracing", feature = "log"))))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_error_fields {
+    ($level:ident, $err:expr, $call:expr, [$($msg:tt)*], [$(($key:ident $fmt:literal $marker:tt $value:expr))*], [$(no_error)? $(error = $mode:ident)? $(attempt = $attempt:expr)?]) => ({
+        $( let _ = &$value; )*
+        $( let _ = &$attempt; )?
+        $crate::__log!($level, $($msg)*)
+    });
+    ($level:ident, $err:expr, $call:expr, $msg:tt, $fields:tt, [reason = $reason:expr]) => ({
+        let _ = &$reason;
+        $crate::__log_error_fields!($level, $err, $call, $msg, $fields, [])
+    });
+}
+
+/// Emit an error message via the selected backend, appending the error (where selected)
+/// and fields as `key=value` followed by the call site location
+#[cfg(not(any(feature = "tracing", all(feature = "defmt", not(feature = "log")))))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_error_fields {
+    ($level:ident, $err:expr, $call:expr, [$($msg:tt)*], [$(($key:ident $fmt:literal $marker:tt $value:expr))*], $extra:tt) => (
+        $crate::__log!($level, concat!("{}{}" $(, " ", stringify!($key), $fmt)*, "{}"),
+            format_args!($($msg)*), $crate::__error_suffix!($err, $extra) $(, $value)*, $crate::__location!())
+    );
+}
+
+/// Emit a log message for an error handled outside of a macro (such as by the
+/// [`ResultExt`](crate::ext::ResultExt) methods), with the location of the caller
+///
+/// The error (where provided) is recorded on `tracing` events and otherwise appended to
+/// messages using `Debug` as with the `debug` mode of `__log_error!`, followed by the location.
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_caller {
+    (default, $($rest:tt)*) => (
+        $crate::__with_default_level!(__log_caller, $($rest)*)
+    );
+    ($level:ident, $msg:expr, $err:expr, $location:expr) => (
+        $crate::__private::tracing::$level!(
+            error = ?$err,
+            location = $crate::__caller_location!($location),
+            "{}", $msg
+        )
+    );
+    ($level:ident, $msg:expr, $location:expr) => (
+        $crate::__private::tracing::$level!(
+            location = $crate::__caller_location!($location),
+            "{}", $msg
+        )
+    );
+}
+
+/// Emit a log message for an error handled outside of a macro via `defmt`, the error
+/// and location are discarded
+#[cfg(all(feature = "defmt", not(any(feature = "tracing", feature = "log"))))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_caller {
+    (default, $($rest:tt)*) => (
+        $crate::__with_default_level!(__log_caller, $($rest)*)
+    );
+    ($level:ident, $msg:expr, $err:expr, $location:expr) => ({
+        let _ = &$err;
+        $crate::__log!($level, "{=str}", $msg)
+    });
+    ($level:ident, $msg:expr, $location:expr) => (
+        $crate::__log_caller!($level, $msg, (), $location)
+    );
+}
+
+/// Emit a log message for an error handled outside of a macro via the selected backend
+#[cfg(not(any(feature = "tracing", all(feature = "defmt", not(feature = "log")))))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_caller {
+    (default, $($rest:tt)*) => (
+        $crate::__with_default_level!(__log_caller, $($rest)*)
+    );
+    ($level:ident, $msg:expr, $err:expr, $location:expr) => (
+        $crate::__log!($level, "{} error={:?}{}", $msg, $err, $crate::__caller_location!($location))
+    );
+    ($level:ident, $msg:expr, $location:expr) => (
+        $crate::__log!($level, "{}{}", $msg, $crate::__caller_location!($location))
+    );
+}
+
+/// Format the location of a caller as a `key=value` field for appending to messages
+#[cfg(all(feature = "location", not(feature = "tracing")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __caller_location {
+    ($location:expr) => (
+        format_args!(" location={}", $location)
+    );
+}
+
+/// Record the location of a caller as a field with `tracing`
+#[cfg(all(feature = "location", feature = "tracing"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __caller_location {
+    ($location:expr) => (
+        $crate::__private::tracing::field::display($location)
+    );
+}
+
+/// Call site location is disabled
+#[cfg(all(not(feature = "location"), not(feature = "tracing")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __caller_location {
+    ($location:expr) => ("");
+}
+
+/// Call site location is disabled
+#[cfg(all(not(feature = "location"), feature = "tracing"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __caller_location {
+    ($location:expr) => ($crate::__private::tracing::field::Empty);
+}
+
+/// Format the error as a `key=value` field for appending to messages
+///
+/// Failed retry attempts append the attempt number and error, and the final failure the
+/// reason retrying stopped and error, where the error must implement `Debug`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __error_suffix {
+    ($err:expr, []) => ("");
+    ($err:expr, [no_error]) => ("");
+    ($err:expr, [error = display]) => (
+        format_args!(" error={}", $err)
+    );
+    ($err:expr, [error = debug]) => (
+        format_args!(" error={:?}", $err)
+    );
+    ($err:expr, [error = chain]) => (
+        format_args!(" error={}", $crate::context::ErrorChain(&$err))
+    );
+    ($err:expr, [attempt = $attempt:expr]) => (
+        format_args!(" attempt={} error={:?}", $attempt, $err)
+    );
+    ($err:expr, [reason = $reason:expr]) => (
+        format_args!(" reason={} error={:?}", $reason, $err)
+    );
+}
+
+/// Format the call site location as `key=value` fields for appending to messages
+#[cfg(feature = "location")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __location {
+    () => (
+        format_args!(" location={}:{}:{} module={}", file!(), line!(), column!(), module_path!())
+    );
+}
+
+/// Call site location is disabled
+#[cfg(not(feature = "location"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __location {
+    () => ("");
+}
+
+/// Fetch the call site column for recording with `tracing` (which records the file, line
+/// and module itself)
+#[cfg(all(feature = "tracing", feature = "location"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __column {
+    () => (column!());
+}
+
+/// Call site location is disabled
+#[cfg(all(feature = "tracing", not(feature = "location")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __column {
+    () => ($crate::__private::tracing::field::Empty);
+}
@@ -0,0 +1,109 @@
+//! Catching panics as errors
+//!
+//! With the `catch_unwind` option to [`handle_error!`](crate::handle_error) and
+//! [`retry_error!`](crate::retry_error) the expression is evaluated under
+//! [`std::panic::catch_unwind`], with panics converted to a [`PanickedError`] (and then
+//! to the expression error type using `From`) to be logged, retried or propagated as
+//! any other error.
+//!
+//! Panics are still reported by the panic hook, and as with `catch_unwind` panics are
+//! only caught where the panic strategy is `unwind`. The expression is treated as
+//! [unwind safe](std::panic::UnwindSafe), so state it mutates may be left inconsistent
+//! following a panic.
+//!
+//! ```
+//! use handle_error::{handle_error, panic::PanickedError};
+//!
+//! // Parser panicking on invalid input
+//! fn parse_header(b: &[u8]) -> Result<u8, std::io::Error> {
+//!     Ok(b[0])
+//! }
+//!
+//! fn read(b: &[u8]) -> Result<u8, std::io::Error> {
+//!     let v = handle_error!(parse_header(b), catch_unwind, "Failed to parse header");
+//!     Ok(v)
+//! }
+//!
+//! assert_eq!(read(&[4]).unwrap(), 4);
+//!
+//! let e = read(&[]).unwrap_err();
+//! let p = e.get_ref().unwrap().downcast_ref::<PanickedError>().unwrap();
+//! assert!(p.message().contains("out of bounds"));
+//! assert_eq!(p.location().file, file!());
+//! ```
+
+use std::any::Any;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::panic::AssertUnwindSafe;
+
+use crate::context::Location;
+
+/// Error produced from a caught panic, with the panic message and the location of the
+/// call site the panic was caught at
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PanickedError {
+    message: String,
+    location: Location,
+}
+
+impl PanickedError {
+    /// Create a panicked error from a panic payload (as returned by `catch_unwind`)
+    pub fn new(payload: Box<dyn Any + Send>, location: Location) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(s) => *s,
+            Err(payload) => match payload.downcast::<&'static str>() {
+                Ok(s) => s.to_string(),
+                Err(_) => "non-string panic payload".to_string(),
+            },
+        };
+
+        Self { message, location }
+    }
+
+    /// Fetch the panic message
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Fetch the location the panic was caught at
+    pub fn location(&self) -> Location {
+        self.location
+    }
+}
+
+impl Display for PanickedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "panicked: {} ({})", self.message, self.location)
+    }
+}
+
+impl Error for PanickedError {}
+
+impl From<PanickedError> for std::io::Error {
+    fn from(e: PanickedError) -> Self {
+        std::io::Error::other(e)
+    }
+}
+
+/// Call the provided fallible function, converting any panic to a [`PanickedError`]
+/// with the provided location
+///
+/// ```
+/// use handle_error::{context::Location, panic::catch_unwind};
+///
+/// let location = Location { file: "main.rs", line: 1, column: 1 };
+/// let r: Result<(), std::io::Error> = catch_unwind(location, || panic!("oh no"));
+///
+/// assert!(r.unwrap_err().to_string().contains("oh no"));
+/// ```
+pub fn catch_unwind<T, E, F>(location: Location, f: F) -> Result<T, E>
+where
+    F: FnOnce() -> Result<T, E>,
+    E: From<PanickedError>,
+{
+    match std::panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(r) => r,
+        Err(payload) => Err(PanickedError::new(payload, location).into()),
+    }
+}
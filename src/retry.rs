@@ -0,0 +1,843 @@
+//! Retry support types
+//!
+//! These are used by the retry macros, with [`RetryPolicy`] controlling which errors
+//! are retried and the delay between attempts, and may be used directly with [`retry`]
+//! (or [`RetryPolicy::run`]) where a policy is configured at runtime or passed around.
+//! See [`retry_error_async!`](crate::retry_error_async) for asynchronous use.
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::backoff::{Backoff, Delays};
+use crate::breaker::{CircuitBreaker, WhileClosed};
+use crate::clock::{Clock, SystemClock};
+use crate::stats::SiteStats;
+
+/// Retry a provided fallible function up to `retries` times, sleeping between attempts
+/// using the provided backoff and clock
+///
+/// This returns the first successful result, or the final error if all attempts fail.
+///
+/// ```
+/// use std::time::Duration;
+/// use handle_error::{backoff::Backoff, clock::ManualClock, retry::retry_with_backoff};
+///
+/// let clock = ManualClock::new();
+/// let backoff = Backoff::linear(Duration::from_secs(1), Duration::from_secs(1));
+///
+/// let r: Result<(), _> = retry_with_backoff(3, &backoff, &clock, || Err("nope"));
+///
+/// assert_eq!(r, Err("nope"));
+/// assert_eq!(clock.elapsed(), Duration::from_secs(1 + 2 + 3));
+/// ```
+pub fn retry_with_backoff<T, E, F, C>(retries: u32, backoff: &Backoff, clock: C, f: F) -> Result<T, E>
+where
+    F: FnMut() -> Result<T, E>,
+    C: Clock,
+{
+    RetryPolicy::new(retries).backoff(backoff.clone()).clock(clock).run(f)
+}
+
+/// Run the provided fallible function under a [`RetryPolicy`]
+///
+/// This is equivalent to [`RetryPolicy::run`], and to `retry_error!` without logging.
+/// As the function is a normal closure, errors may be handled with `?` inside it and
+/// early `return`s leave only the current attempt.
+///
+/// ```
+/// use std::time::Duration;
+/// use handle_error::retry::{retry, Retry};
+///
+/// let policy = Retry::default().attempts(3).backoff(Duration::from_millis(1));
+///
+/// let mut n = 0;
+/// let r = retry(&policy, || {
+///     n += 1;
+///     let v: u32 = if n < 3 { "nope" } else { "3" }.parse()?;
+///     Ok::<_, std::num::ParseIntError>(v)
+/// });
+///
+/// assert_eq!(r, Ok(3));
+/// ```
+pub fn retry<T, E, F, P, C, H>(policy: &RetryPolicy<P, C, H>, f: F) -> Result<T, E>
+where
+    F: FnMut() -> Result<T, E>,
+    P: RetryPredicate<E>,
+    C: Clock,
+    H: RetryHook<E>,
+{
+    policy.run(f)
+}
+
+/// Alias for [`RetryPolicy`] for use as a builder, `Retry::default().attempts(3).run(...)`
+pub type Retry<P = Always, C = SystemClock, H = NoHook> = RetryPolicy<P, C, H>;
+
+/// Policy controlling how (and which) errors are retried
+///
+/// By default all errors are retried without delay, [`RetryPolicy::when`] and
+/// [`RetryPolicy::transient`] restrict retries to matching errors,
+/// [`RetryPolicy::backoff`] sets the delay between attempts, and
+/// [`RetryPolicy::deadline`] bounds the total time spent retrying.
+///
+/// ```
+/// use std::io::{Error, ErrorKind};
+/// use handle_error::retry::RetryPolicy;
+///
+/// let mut attempts = 0;
+/// let r: Result<(), Error> = RetryPolicy::new(3).transient().run(|| {
+///     attempts += 1;
+///     Err(ErrorKind::PermissionDenied.into())
+/// });
+///
+/// assert!(r.is_err());
+/// assert_eq!(attempts, 1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct RetryPolicy<P = Always, C = SystemClock, H = NoHook> {
+    retries: u32,
+    backoff: Backoff,
+    deadline: Option<Deadline>,
+    predicate: P,
+    clock: C,
+    hook: H,
+}
+
+impl RetryPolicy {
+    /// Create a new policy retrying all errors up to `retries` times (making up to
+    /// `retries + 1` attempts)
+    pub fn new(retries: u32) -> Self {
+        Self {
+            retries,
+            backoff: Backoff::None,
+            deadline: None,
+            predicate: Always,
+            clock: SystemClock,
+            hook: NoHook,
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Create a policy making a single attempt, see [`RetryPolicy::attempts`]
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl<P, C, H> RetryPolicy<P, C, H> {
+    /// Set the maximum number of attempts, including the first (so `attempts - 1` retries)
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.retries = attempts.saturating_sub(1);
+        self
+    }
+
+    /// Set the backoff used to delay between attempts
+    pub fn backoff(mut self, backoff: impl Into<Backoff>) -> Self {
+        self.backoff = backoff.into();
+        self
+    }
+
+    /// Set a deadline after which no further attempts are made, either as a [`Duration`]
+    /// from the start of the first attempt or an [`Instant`]
+    ///
+    /// Retrying stops once the next attempt would start after the deadline, regardless
+    /// of the number of retries remaining.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use handle_error::{clock::ManualClock, retry::{RetryPolicy, StopReason}};
+    ///
+    /// let clock = ManualClock::new();
+    /// let r: Result<(), _> = RetryPolicy::new(10)
+    ///     .backoff(Duration::from_secs(1))
+    ///     .deadline(Duration::from_millis(2500))
+    ///     .clock(&clock)
+    ///     .run_all(|| Err("nope"));
+    ///
+    /// let e = r.unwrap_err();
+    /// assert_eq!(e.attempts().len(), 3);
+    /// assert_eq!(e.reason(), StopReason::Deadline);
+    /// ```
+    pub fn deadline(mut self, deadline: impl Into<Deadline>) -> Self {
+        self.deadline = Some(deadline.into());
+        self
+    }
+
+    /// Set the clock used to sleep between blocking attempts
+    pub fn clock<C2: Clock>(self, clock: C2) -> RetryPolicy<P, C2, H> {
+        RetryPolicy {
+            retries: self.retries,
+            backoff: self.backoff,
+            deadline: self.deadline,
+            predicate: self.predicate,
+            clock,
+            hook: self.hook,
+        }
+    }
+
+    /// Only retry errors matching the provided predicate (see [`RetryPredicate`])
+    pub fn when<P2>(self, predicate: P2) -> RetryPolicy<P2, C, H> {
+        RetryPolicy {
+            retries: self.retries,
+            backoff: self.backoff,
+            deadline: self.deadline,
+            predicate,
+            clock: self.clock,
+            hook: self.hook,
+        }
+    }
+
+    /// Only retry errors classified as [`Transient`]
+    pub fn transient(self) -> RetryPolicy<IfTransient, C, H> {
+        self.when(IfTransient)
+    }
+
+    /// Stop retrying while the provided [`CircuitBreaker`] is rejecting calls, with
+    /// [`StopReason::CircuitOpen`]
+    ///
+    /// This does not route attempts through the breaker, see [`CircuitBreaker::retry`].
+    pub fn breaker<C2>(self, breaker: &CircuitBreaker<C2>) -> RetryPolicy<WhileClosed<'_, P, C2>, C, H> {
+        RetryPolicy {
+            retries: self.retries,
+            backoff: self.backoff,
+            deadline: self.deadline,
+            predicate: WhileClosed::new(breaker, self.predicate),
+            clock: self.clock,
+            hook: self.hook,
+        }
+    }
+
+    /// Call the provided hook with the attempt number and error for each failed
+    /// attempt that will be retried
+    ///
+    /// ```
+    /// use std::cell::Cell;
+    /// use handle_error::retry::RetryPolicy;
+    ///
+    /// let retried = Cell::new(0);
+    /// let r: Result<(), &str> = RetryPolicy::new(2)
+    ///     .on_retry(|attempt, _e| retried.set(attempt))
+    ///     .run(|| Err("nope"));
+    ///
+    /// assert!(r.is_err());
+    /// assert_eq!(retried.get(), 2);
+    /// ```
+    pub fn on_retry<E, H2>(self, hook: H2) -> RetryPolicy<P, C, H2>
+    where
+        H2: Fn(u32, &E),
+    {
+        RetryPolicy {
+            retries: self.retries,
+            backoff: self.backoff,
+            deadline: self.deadline,
+            predicate: self.predicate,
+            clock: self.clock,
+            hook,
+        }
+    }
+
+    /// Fetch the hook called on failed attempts
+    pub fn hook(&self) -> &H {
+        &self.hook
+    }
+
+    /// Fetch the maximum number of retries
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Fetch the maximum number of attempts, including the first
+    pub fn max_attempts(&self) -> u32 {
+        self.retries.saturating_add(1)
+    }
+
+    /// Fetch an iterator over the delays between attempts
+    pub fn delays(&self) -> Delays {
+        self.backoff.delays()
+    }
+
+    /// Check whether the provided error should be retried under this policy
+    pub fn should_retry<E>(&self, error: &E) -> bool
+    where
+        P: RetryPredicate<E>,
+    {
+        self.predicate.should_retry(error)
+    }
+
+    /// Fetch the current instant from the policy clock
+    pub fn now(&self) -> Instant
+    where
+        C: Clock,
+    {
+        self.clock.now()
+    }
+
+    /// Sleep for the provided duration using the policy clock
+    pub fn sleep(&self, duration: Duration)
+    where
+        C: Clock,
+    {
+        self.clock.sleep(duration)
+    }
+
+    /// Run the provided fallible function under this policy, sleeping between attempts
+    ///
+    /// This returns the first successful result, or the final error where all attempts
+    /// fail or an error is not retryable.
+    pub fn run<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        F: FnMut() -> Result<T, E>,
+        P: RetryPredicate<E>,
+        C: Clock,
+        H: RetryHook<E>,
+    {
+        self.run_with(Last, f)
+    }
+
+    /// Run the provided fallible function under this policy, returning the errors from
+    /// all attempts on failure
+    ///
+    /// ```
+    /// use handle_error::retry::RetryPolicy;
+    ///
+    /// let mut n = 0;
+    /// let r: Result<(), _> = RetryPolicy::new(2).run_all(|| {
+    ///     n += 1;
+    ///     Err(n)
+    /// });
+    ///
+    /// let errors: Vec<_> = r.unwrap_err().attempts().iter().map(|a| a.error).collect();
+    /// assert_eq!(errors, vec![1, 2, 3]);
+    /// ```
+    pub fn run_all<T, E, F>(&self, f: F) -> Result<T, RetryError<E>>
+    where
+        F: FnMut() -> Result<T, E>,
+        P: RetryPredicate<E>,
+        C: Clock,
+        H: RetryHook<E>,
+    {
+        self.run_with(RetryError::new(), f)
+    }
+
+    /// Determine whether to retry following a failed attempt, returning the delay before
+    /// the next attempt or the reason retrying should stop
+    ///
+    /// This is used to implement [`RetryState`].
+    pub fn next_delay<E>(&self, attempt: u32, start: Instant, error: &E, delays: &mut Delays) -> Result<Duration, StopReason>
+    where
+        P: RetryPredicate<E>,
+        C: Clock,
+    {
+        if let Some(reason) = self.predicate.stop_reason(error) {
+            return Err(reason);
+        }
+        if attempt > self.retries {
+            return Err(StopReason::Attempts);
+        }
+
+        let delay = delays.next().unwrap_or_default();
+
+        let deadline = match self.deadline {
+            Some(Deadline::After(d)) => start.checked_add(d),
+            Some(Deadline::At(i)) => Some(i),
+            None => None,
+        };
+        if let Some(d) = deadline {
+            // Delays too long to represent as an instant (such as a saturated backoff)
+            // cannot complete before the deadline
+            match self.clock.now().checked_add(delay) {
+                Some(next) if next <= d => (),
+                _ => return Err(StopReason::Deadline),
+            }
+        }
+
+        Ok(delay)
+    }
+
+    /// Run the provided fallible function under this policy, passing failed attempts
+    /// to the provided [`Collect`] implementation to build the returned error
+    pub fn run_with<T, E, F, R>(&self, collect: R, mut f: F) -> Result<T, R::Output>
+    where
+        F: FnMut() -> Result<T, E>,
+        P: RetryPredicate<E>,
+        C: Clock,
+        R: Collect<E>,
+        H: RetryHook<E>,
+    {
+        let mut state = self.start(collect);
+
+        loop {
+            match f() {
+                Ok(v) => {
+                    state.on_success();
+                    return Ok(v);
+                }
+                Err(e) => self.sleep(state.on_failure(e)?),
+            }
+        }
+    }
+
+    /// Start an operation retried under this policy, passing failed attempts to the
+    /// provided [`Collect`] implementation, see [`RetryState`]
+    pub fn start<R>(&self, collect: R) -> RetryState<'_, P, C, H, R>
+    where
+        C: Clock,
+    {
+        RetryState {
+            policy: self,
+            collect: Some(collect),
+            delays: self.delays(),
+            start: self.clock.now(),
+            attempt: 1,
+            stats: None,
+        }
+    }
+}
+
+/// State of an operation retried under a [`RetryPolicy`], used to make attempts one at
+/// a time where they can not be made from a single closure
+///
+/// This implements [`RetryPolicy::run_with`] and the retry macros, with attempts made
+/// by the caller and reported with [`RetryState::on_success`] or
+/// [`RetryState::on_failure`], which returns the delay before the next attempt or the
+/// error to return once retrying stops.
+///
+/// ```
+/// use handle_error::retry::{Last, RetryPolicy};
+///
+/// let policy = RetryPolicy::new(2);
+/// let mut state = policy.start(Last);
+///
+/// let r: Result<(), &str> = loop {
+///     match Err("nope") {
+///         Ok(v) => {
+///             state.on_success();
+///             break Ok(v);
+///         }
+///         Err(e) => match state.on_failure(e) {
+///             Ok(delay) => policy.sleep(delay),
+///             Err(e) => break Err(e),
+///         },
+///     }
+/// };
+///
+/// assert_eq!(r, Err("nope"));
+/// assert_eq!(state.attempt(), 3);
+/// ```
+#[derive(Debug)]
+pub struct RetryState<'a, P, C, H, R> {
+    policy: &'a RetryPolicy<P, C, H>,
+    collect: Option<R>,
+    delays: Delays,
+    start: Instant,
+    attempt: u32,
+    stats: Option<&'static SiteStats>,
+}
+
+impl<'a, P, C, H, R> RetryState<'a, P, C, H, R> {
+    /// Record the outcome of the operation in the provided call site counters
+    pub fn stats(mut self, stats: &'static SiteStats) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    /// Fetch the number of the current attempt, starting from 1
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Record a successful attempt
+    pub fn on_success(&self) {
+        if let Some(stats) = self.stats {
+            stats.retried(self.attempt, true);
+        }
+    }
+
+    /// Record a failed attempt, returning the delay before the next attempt or the error
+    /// built by the [`Collect`] implementation where retrying stops
+    ///
+    /// The policy hook is called for each attempt that will be retried. This panics if
+    /// called once retrying has stopped.
+    pub fn on_failure<E>(&mut self, error: E) -> Result<Duration, R::Output>
+    where
+        P: RetryPredicate<E>,
+        C: Clock,
+        H: RetryHook<E>,
+        R: Collect<E>,
+    {
+        self.on_failure_with(error, |_e, _reason| ())
+    }
+
+    /// Record a failed attempt as for [`RetryState::on_failure`], first calling `log` with
+    /// the error and the reason retrying stopped (or `None` where it will be retried)
+    pub fn on_failure_with<E, L>(&mut self, error: E, log: L) -> Result<Duration, R::Output>
+    where
+        P: RetryPredicate<E>,
+        C: Clock,
+        H: RetryHook<E>,
+        R: Collect<E>,
+        L: FnOnce(&E, Option<StopReason>),
+    {
+        let attempt = self.attempt;
+
+        match self.policy.next_delay(attempt, self.start, &error, &mut self.delays) {
+            Ok(d) => {
+                self.policy.hook.on_retry(attempt, &error);
+                log(&error, None);
+
+                let a = Attempt::new(attempt, self.policy.now() - self.start, error);
+                self.collect.as_mut().expect("retrying has stopped").push(a);
+                self.attempt += 1;
+                Ok(d)
+            }
+            Err(reason) => {
+                log(&error, Some(reason));
+                if let Some(stats) = self.stats {
+                    stats.retried(attempt, false);
+                }
+
+                let a = Attempt::new(attempt, self.policy.now() - self.start, error);
+                let collect = self.collect.take().expect("retrying has stopped");
+                Err(collect.finish(a, reason))
+            }
+        }
+    }
+}
+
+/// Deadline for retries, see [`RetryPolicy::deadline`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Deadline {
+    /// Duration from the start of the first attempt
+    After(Duration),
+    /// Fixed instant
+    At(Instant),
+}
+
+impl From<Duration> for Deadline {
+    fn from(d: Duration) -> Self {
+        Deadline::After(d)
+    }
+}
+
+impl From<Instant> for Deadline {
+    fn from(i: Instant) -> Self {
+        Deadline::At(i)
+    }
+}
+
+/// Reason retrying stopped
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StopReason {
+    /// All permitted attempts failed
+    #[default]
+    Attempts,
+    /// The next attempt would start after the deadline
+    Deadline,
+    /// The error was not retryable under the policy
+    NotRetryable,
+    /// The circuit breaker is rejecting calls, see [`RetryPolicy::breaker`]
+    CircuitOpen,
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Attempts => write!(f, "attempt limit reached"),
+            StopReason::Deadline => write!(f, "deadline exceeded"),
+            StopReason::NotRetryable => write!(f, "error not retryable"),
+            StopReason::CircuitOpen => write!(f, "circuit breaker open"),
+        }
+    }
+}
+
+/// Hook called for each failed attempt that will be retried
+///
+/// This is implemented for [`NoHook`] and any `Fn(u32, &E)`, see [`RetryPolicy::on_retry`].
+pub trait RetryHook<E> {
+    /// Called with the attempt number (starting from 1) and error of a failed attempt
+    fn on_retry(&self, attempt: u32, error: &E);
+}
+
+impl<E, F> RetryHook<E> for F
+where
+    F: Fn(u32, &E),
+{
+    fn on_retry(&self, attempt: u32, error: &E) {
+        (self)(attempt, error)
+    }
+}
+
+/// Hook that does nothing
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoHook;
+
+impl<E> RetryHook<E> for NoHook {
+    fn on_retry(&self, _attempt: u32, _error: &E) {}
+}
+
+/// Record of a single failed attempt
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attempt<E> {
+    /// Attempt number, starting from 1
+    pub attempt: u32,
+    /// Time elapsed from the start of the first attempt to this failure
+    pub elapsed: Duration,
+    /// Error returned by the attempt
+    pub error: E,
+}
+
+impl<E> Attempt<E> {
+    /// Create a new attempt record
+    pub fn new(attempt: u32, elapsed: Duration, error: E) -> Self {
+        Self { attempt, elapsed, error }
+    }
+}
+
+/// Collector for failed attempts, building the error returned when retries are exhausted
+///
+/// This is implemented by [`Last`] to return only the final error, and [`RetryError`]
+/// to return the errors from all attempts.
+pub trait Collect<E> {
+    /// Error type returned when all attempts fail
+    type Output;
+
+    /// Record a failed attempt that will be retried
+    fn push(&mut self, attempt: Attempt<E>);
+
+    /// Record the final failed attempt and the reason retrying stopped, and build the
+    /// returned error
+    fn finish(self, attempt: Attempt<E>, reason: StopReason) -> Self::Output;
+}
+
+/// Return only the error from the final attempt
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Last;
+
+impl<E> Collect<E> for Last {
+    type Output = E;
+
+    fn push(&mut self, _attempt: Attempt<E>) {}
+
+    fn finish(self, attempt: Attempt<E>, _reason: StopReason) -> E {
+        attempt.error
+    }
+}
+
+/// Error containing the failures from all retry attempts
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryError<E> {
+    attempts: Vec<Attempt<E>>,
+    reason: StopReason,
+}
+
+impl<E> RetryError<E> {
+    /// Create a new empty retry error
+    pub fn new() -> Self {
+        Self {
+            attempts: Vec::new(),
+            reason: StopReason::Attempts,
+        }
+    }
+
+    /// Fetch the failed attempts, in order
+    pub fn attempts(&self) -> &[Attempt<E>] {
+        &self.attempts
+    }
+
+    /// Fetch the reason retrying stopped
+    pub fn reason(&self) -> StopReason {
+        self.reason
+    }
+
+    /// Fetch the error from the final attempt
+    pub fn last(&self) -> Option<&E> {
+        self.attempts.last().map(|a| &a.error)
+    }
+
+    /// Consume the retry error, returning the failed attempts
+    pub fn into_attempts(self) -> Vec<Attempt<E>> {
+        self.attempts
+    }
+}
+
+impl<E> Default for RetryError<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> Collect<E> for RetryError<E> {
+    type Output = Self;
+
+    fn push(&mut self, attempt: Attempt<E>) {
+        self.attempts.push(attempt);
+    }
+
+    fn finish(mut self, attempt: Attempt<E>, reason: StopReason) -> Self {
+        self.attempts.push(attempt);
+        self.reason = reason;
+        self
+    }
+}
+
+impl<E: Display> Display for RetryError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} attempts failed ({})", self.attempts.len(), self.reason)?;
+        for a in &self.attempts {
+            write!(f, "; attempt {} ({:?}): {}", a.attempt, a.elapsed, a.error)?;
+        }
+        Ok(())
+    }
+}
+
+impl<E: Error + 'static> Error for RetryError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.last().map(|e| e as &(dyn Error + 'static))
+    }
+}
+
+/// Predicate determining whether an error should be retried
+///
+/// This is implemented for [`Always`], [`IfTransient`], and any `Fn(&E) -> bool`.
+pub trait RetryPredicate<E> {
+    /// Returns true if the provided error should be retried
+    fn should_retry(&self, error: &E) -> bool;
+
+    /// Returns the reason retrying should stop following the provided error, if any
+    ///
+    /// By default this is [`StopReason::NotRetryable`] where the error should not be retried.
+    fn stop_reason(&self, error: &E) -> Option<StopReason> {
+        if self.should_retry(error) {
+            None
+        } else {
+            Some(StopReason::NotRetryable)
+        }
+    }
+}
+
+impl<E, F> RetryPredicate<E> for F
+where
+    F: Fn(&E) -> bool,
+{
+    fn should_retry(&self, error: &E) -> bool {
+        (self)(error)
+    }
+}
+
+/// Retry all errors
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Always;
+
+impl<E> RetryPredicate<E> for Always {
+    fn should_retry(&self, _error: &E) -> bool {
+        true
+    }
+}
+
+/// Retry only errors classified as [`Transient`]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IfTransient;
+
+impl<E: Transient> RetryPredicate<E> for IfTransient {
+    fn should_retry(&self, error: &E) -> bool {
+        error.is_transient()
+    }
+}
+
+/// Classification of errors that may succeed if retried
+pub trait Transient {
+    /// Returns true if the error is transient and the operation may be retried
+    fn is_transient(&self) -> bool;
+}
+
+impl Transient for std::io::ErrorKind {
+    fn is_transient(&self) -> bool {
+        use std::io::ErrorKind::*;
+
+        matches!(
+            self,
+            Interrupted | WouldBlock | TimedOut | ConnectionReset | ConnectionAborted
+        )
+    }
+}
+
+impl Transient for std::io::Error {
+    fn is_transient(&self) -> bool {
+        self.kind().is_transient()
+    }
+}
+
+/// Asynchronous sleep used to delay between retry attempts
+///
+/// This is implemented for [`TokioSleep`] and [`AsyncStdSleep`] (with the `tokio` and
+/// `async-std` features respectively), as well as for any `Fn(Duration) -> impl Future`
+/// to support other runtimes.
+pub trait AsyncSleep {
+    /// Future returned by [`AsyncSleep::sleep`]
+    type Sleep: Future<Output = ()>;
+
+    /// Create a future that completes after the provided duration
+    fn sleep(&self, duration: Duration) -> Self::Sleep;
+}
+
+impl<F, S> AsyncSleep for F
+where
+    F: Fn(Duration) -> S,
+    S: Future<Output = ()>,
+{
+    type Sleep = S;
+
+    fn sleep(&self, duration: Duration) -> Self::Sleep {
+        (self)(duration)
+    }
+}
+
+/// Sleep that completes immediately, used where no backoff is configured
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoSleep;
+
+impl AsyncSleep for NoSleep {
+    type Sleep = std::future::Ready<()>;
+
+    fn sleep(&self, _duration: Duration) -> Self::Sleep {
+        std::future::ready(())
+    }
+}
+
+/// Sleep using the `tokio` timer
+#[cfg(feature = "tokio")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioSleep;
+
+#[cfg(feature = "tokio")]
+impl AsyncSleep for TokioSleep {
+    type Sleep = tokio::time::Sleep;
+
+    fn sleep(&self, duration: Duration) -> Self::Sleep {
+        tokio::time::sleep(duration)
+    }
+}
+
+/// Sleep using the `async-std` timer
+#[cfg(feature = "async-std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AsyncStdSleep;
+
+#[cfg(feature = "async-std")]
+impl AsyncSleep for AsyncStdSleep {
+    type Sleep = std::pin::Pin<Box<dyn Future<Output = ()> + Send>>;
+
+    fn sleep(&self, duration: Duration) -> Self::Sleep {
+        Box::pin(async_std::task::sleep(duration))
+    }
+}
+
+/// Default sleep used where a delay is provided without a sleep implementation
+#[cfg(feature = "tokio")]
+pub type DefaultSleep = TokioSleep;
+
+/// Default sleep used where a delay is provided without a sleep implementation
+#[cfg(all(feature = "async-std", not(feature = "tokio")))]
+pub type DefaultSleep = AsyncStdSleep;
@@ -0,0 +1,203 @@
+//! Per call site error counters
+//!
+//! Each macro call site maintains counters of the errors handled (or retries that
+//! ultimately failed), retried attempts, and retries that eventually succeeded. Sites are
+//! registered on first use and may be listed with [`sites`], or exported in the Prometheus
+//! text format with [`prometheus`].
+//!
+//! With the `metrics` feature counters are also emitted through the
+//! [metrics](https://docs.rs/metrics) facade as `handle_error_failures_total`,
+//! `handle_error_retries_total` and `handle_error_recoveries_total`, labelled with the
+//! macro and call site.
+//!
+//! ```
+//! use handle_error::{handle_error, stats};
+//!
+//! fn open(path: &str) -> Result<std::fs::File, std::io::Error> {
+//!     let f = handle_error!(std::fs::File::open(path), "Failed to open {}", path);
+//!     Ok(f)
+//! }
+//!
+//! for _ in 0..3 {
+//!     let _ = open("/does/not/exist");
+//! }
+//!
+//! let site = stats::sites().into_iter().find(|s| s.macro_name() == "handle_error").unwrap();
+//! assert_eq!(site.failures(), 3);
+//! assert_eq!(site.file(), file!());
+//!
+//! assert!(stats::prometheus().contains("handle_error_failures_total{macro=\"handle_error\""));
+//! ```
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+static REGISTRY: Mutex<Vec<&'static SiteStats>> = Mutex::new(Vec::new());
+
+/// Counters for a single macro call site
+///
+/// These are created as a `static` by each macro call site.
+#[derive(Debug)]
+pub struct SiteStats {
+    macro_name: &'static str,
+    file: &'static str,
+    line: u32,
+    column: u32,
+    module: &'static str,
+    failures: AtomicUsize,
+    retries: AtomicUsize,
+    recoveries: AtomicUsize,
+    registered: AtomicBool,
+}
+
+impl SiteStats {
+    /// Create counters for a call site
+    pub const fn new(macro_name: &'static str, file: &'static str, line: u32, column: u32, module: &'static str) -> Self {
+        Self {
+            macro_name,
+            file,
+            line,
+            column,
+            module,
+            failures: AtomicUsize::new(0),
+            retries: AtomicUsize::new(0),
+            recoveries: AtomicUsize::new(0),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    /// Name of the macro used at this site
+    pub fn macro_name(&self) -> &'static str {
+        self.macro_name
+    }
+
+    /// Source file of this site
+    pub fn file(&self) -> &'static str {
+        self.file
+    }
+
+    /// Line number of this site
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// Column number of this site
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+
+    /// Module path of this site
+    pub fn module(&self) -> &'static str {
+        self.module
+    }
+
+    /// Number of errors handled, or retried operations that ultimately failed
+    pub fn failures(&self) -> usize {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    /// Number of failed attempts that were retried
+    pub fn retries(&self) -> usize {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    /// Number of retried operations that eventually succeeded
+    pub fn recoveries(&self) -> usize {
+        self.recoveries.load(Ordering::Relaxed)
+    }
+
+    /// Record a handled error
+    pub fn failure(&'static self) {
+        self.register();
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.emit("handle_error_failures_total", 1);
+    }
+
+    /// Record the outcome of a retried operation after the provided number of attempts
+    pub fn retried(&'static self, attempts: u32, success: bool) {
+        let retries = attempts.saturating_sub(1) as usize;
+        if retries == 0 && success {
+            return;
+        }
+
+        self.register();
+        if retries > 0 {
+            self.retries.fetch_add(retries, Ordering::Relaxed);
+            #[cfg(feature = "metrics")]
+            self.emit("handle_error_retries_total", retries as u64);
+        }
+        if !success {
+            self.failure();
+        } else {
+            self.recoveries.fetch_add(1, Ordering::Relaxed);
+            #[cfg(feature = "metrics")]
+            self.emit("handle_error_recoveries_total", 1);
+        }
+    }
+
+    fn register(&'static self) {
+        if !self.registered.swap(true, Ordering::AcqRel) {
+            REGISTRY.lock().unwrap().push(self);
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    fn emit(&self, name: &'static str, n: u64) {
+        ::metrics::counter!(name,
+            "macro" => self.macro_name,
+            "file" => self.file,
+            "line" => self.line.to_string(),
+            "column" => self.column.to_string(),
+            "module" => self.module,
+        ).increment(n);
+    }
+}
+
+/// Fetch the counters for all sites that have recorded an error
+pub fn sites() -> Vec<&'static SiteStats> {
+    REGISTRY.lock().unwrap().clone()
+}
+
+/// Exported counter name, help text and accessor
+type Counter = (&'static str, &'static str, fn(&SiteStats) -> usize);
+
+const COUNTERS: [Counter; 3] = [
+    ("handle_error_failures_total", "Errors handled or retries failed per call site", SiteStats::failures),
+    ("handle_error_retries_total", "Failed attempts retried per call site", SiteStats::retries),
+    ("handle_error_recoveries_total", "Retries eventually succeeding per call site", SiteStats::recoveries),
+];
+
+/// Export the counters for all sites in the Prometheus text exposition format
+pub fn prometheus() -> String {
+    let sites = sites();
+    let mut s = String::new();
+
+    for (name, help, value) in COUNTERS.iter() {
+        let _ = writeln!(s, "# HELP {} {}", name, help);
+        let _ = writeln!(s, "# TYPE {} counter", name);
+        for site in &sites {
+            let _ = writeln!(s, "{}{{macro=\"{}\",file=\"{}\",line=\"{}\",column=\"{}\",module=\"{}\"}} {}",
+                name, site.macro_name, escape(site.file), site.line, site.column, site.module, value(site));
+        }
+    }
+
+    s
+}
+
+/// Escape a label value for the Prometheus text format
+fn escape(v: &str) -> String {
+    v.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Fetch the counters for the current call site
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __site_stats {
+    ($name:ident) => ({
+        static STATS: $crate::stats::SiteStats = $crate::stats::SiteStats::new(
+            stringify!($name), file!(), line!(), column!(), module_path!());
+        &STATS
+    });
+}
@@ -0,0 +1,120 @@
+use std::io::{Error, ErrorKind};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use handle_error::breaker::{CircuitBreaker, CircuitOpen, State};
+use handle_error::clock::ManualClock;
+use handle_error::retry::StopReason;
+use handle_error::retry_error;
+
+const COOL_DOWN: Duration = Duration::from_secs(30);
+
+fn is_open(r: Result<(), Error>) -> bool {
+    r.is_err_and(|e| e.get_ref().is_some_and(|e| e.is::<CircuitOpen>()))
+}
+
+#[test]
+fn failed_trial_call_reopens() {
+    let clock = ManualClock::new();
+    let breaker = CircuitBreaker::new(1, COOL_DOWN).clock(&clock);
+
+    let _: Result<(), Error> = breaker.call(|| Err(ErrorKind::TimedOut.into()));
+    assert_eq!(breaker.state(), State::Open);
+
+    clock.advance(COOL_DOWN);
+    assert_eq!(breaker.state(), State::HalfOpen);
+
+    let r: Result<(), Error> = breaker.call(|| Err(ErrorKind::TimedOut.into()));
+    assert_eq!(r.unwrap_err().kind(), ErrorKind::TimedOut);
+    assert_eq!(breaker.state(), State::Open);
+    assert_eq!(breaker.failures(), 2);
+
+    // The cool-down restarts from the failed trial call
+    assert!(is_open(breaker.call(|| Ok(()))));
+    clock.advance(COOL_DOWN);
+    assert!(breaker.call(|| Ok::<_, Error>(())).is_ok());
+    assert_eq!(breaker.state(), State::Closed);
+}
+
+#[test]
+fn panicking_call_recorded_as_failure() {
+    let clock = ManualClock::new();
+    let breaker = CircuitBreaker::new(1, COOL_DOWN).clock(&clock);
+
+    let r = panic::catch_unwind(AssertUnwindSafe(|| {
+        breaker.call(|| -> Result<(), Error> { panic!("call panicked") })
+    }));
+    assert!(r.is_err());
+    assert_eq!(breaker.state(), State::Open);
+
+    // A panicking trial call re-opens the breaker rather than leaving it half-open
+    clock.advance(COOL_DOWN);
+    let r = panic::catch_unwind(AssertUnwindSafe(|| {
+        breaker.call(|| -> Result<(), Error> { panic!("trial call panicked") })
+    }));
+    assert!(r.is_err());
+    assert_eq!(breaker.state(), State::Open);
+
+    clock.advance(COOL_DOWN);
+    assert!(breaker.call(|| Ok::<_, Error>(())).is_ok());
+}
+
+#[test]
+fn single_trial_call_while_half_open() {
+    let clock = ManualClock::new();
+    let breaker = CircuitBreaker::new(1, COOL_DOWN).clock(&clock);
+
+    let _: Result<(), Error> = breaker.call(|| Err(ErrorKind::TimedOut.into()));
+    clock.advance(COOL_DOWN);
+
+    let (entered_tx, entered_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel();
+    let breaker = &breaker;
+
+    thread::scope(|s| {
+        let trial = s.spawn(move || {
+            breaker.call(|| {
+                entered_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+                Ok::<_, Error>(())
+            })
+        });
+
+        // Calls made while the trial call is in progress are rejected
+        entered_rx.recv().unwrap();
+        assert_eq!(breaker.state(), State::HalfOpen);
+        let concurrent = s.spawn(move || breaker.call(|| Ok(())));
+        assert!(is_open(concurrent.join().unwrap()));
+
+        release_tx.send(()).unwrap();
+        assert!(trial.join().unwrap().is_ok());
+    });
+
+    assert_eq!(breaker.state(), State::Closed);
+}
+
+#[test]
+fn retries_stop_when_breaker_opens() {
+    let breaker = CircuitBreaker::new(2, COOL_DOWN);
+
+    let mut attempts = 0;
+    let r: Result<(), _> = retry_error!(5, all, breaker = &breaker, {
+        attempts += 1;
+        Err::<(), Error>(ErrorKind::TimedOut.into())
+    }, "Failed to do something");
+
+    let e = r.unwrap_err();
+    assert_eq!(attempts, 2);
+    assert_eq!(e.attempts().len(), 2);
+    assert_eq!(e.reason(), StopReason::CircuitOpen);
+}
+
+#[test]
+fn breaker_in_a_static() {
+    static BREAKER: CircuitBreaker = CircuitBreaker::new(1, COOL_DOWN);
+
+    let _: Result<(), Error> = BREAKER.call(|| Err(ErrorKind::TimedOut.into()));
+    assert_eq!(BREAKER.state(), State::Open);
+}
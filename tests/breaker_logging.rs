@@ -0,0 +1,50 @@
+#![cfg(all(feature = "log", not(feature = "tracing")))]
+
+use std::io::{Error, ErrorKind};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use handle_error::breaker::{CircuitBreaker, State};
+
+static BREAKER: CircuitBreaker = CircuitBreaker::new(1, Duration::ZERO);
+static PANIC: AtomicBool = AtomicBool::new(false);
+
+/// Logger inspecting the breaker it is logging for, and panicking where requested
+struct InspectingLogger;
+
+impl log::Log for InspectingLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, _record: &log::Record) {
+        let _ = BREAKER.state();
+        if PANIC.swap(false, Ordering::SeqCst) {
+            panic!("logger panicked");
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: InspectingLogger = InspectingLogger;
+
+#[test]
+fn transitions_logged_outside_the_lock() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    // A logger calling back into the breaker does not deadlock
+    let _: Result<(), Error> = BREAKER.call(|| Err(ErrorKind::TimedOut.into()));
+    assert_eq!(BREAKER.state(), State::HalfOpen);
+
+    // And a logger panicking on a transition does not poison it
+    PANIC.store(true, Ordering::SeqCst);
+    let r = panic::catch_unwind(AssertUnwindSafe(|| BREAKER.call(|| Ok::<_, Error>(()))));
+    assert!(r.is_err());
+    assert_eq!(BREAKER.state(), State::HalfOpen);
+
+    assert!(BREAKER.call(|| Ok::<_, Error>(())).is_ok());
+    assert_eq!(BREAKER.state(), State::Closed);
+}
@@ -0,0 +1,42 @@
+use std::cell::Cell;
+
+use handle_error::{context::ContextError, context_error};
+
+struct NullLogger;
+
+impl log::Log for NullLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, _record: &log::Record) {}
+
+    fn flush(&self) {}
+}
+
+static LOGGER: NullLogger = NullLogger;
+
+fn read(n: &Cell<u32>) -> Result<(), ContextError<std::io::Error>> {
+    let next = || {
+        n.set(n.get() + 1);
+        n.get()
+    };
+
+    context_error!(Err(std::io::ErrorKind::NotFound.into()), "Read {} failed", next(); attempt = 1);
+    Ok(())
+}
+
+#[test]
+fn message_arguments_evaluated_once() {
+    log::set_logger(&LOGGER).unwrap();
+
+    for level in [log::LevelFilter::Trace, log::LevelFilter::Off] {
+        log::set_max_level(level);
+
+        let n = Cell::new(0);
+        let e = read(&n).unwrap_err();
+
+        assert_eq!(n.get(), 1);
+        assert_eq!(e.message(), "Read 1 failed");
+    }
+}
@@ -0,0 +1,41 @@
+#![cfg(all(feature = "log", not(feature = "tracing")))]
+
+use std::sync::Mutex;
+
+use handle_error::ext::{OptionExt, ResultExt};
+
+struct CaptureLogger(Mutex<Vec<String>>);
+
+impl log::Log for CaptureLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.0.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CaptureLogger = CaptureLogger(Mutex::new(Vec::new()));
+
+#[test]
+fn logs_error_and_caller() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let _ = "nope".parse::<u32>().log_err("Failed to parse value");
+    let _ = None::<u32>.log_none("Missing value");
+
+    let messages = LOGGER.0.lock().unwrap().clone();
+    assert_eq!(messages.len(), 2);
+
+    assert!(messages[0].starts_with("Failed to parse value error=ParseIntError"), "{}", messages[0]);
+    assert!(messages[1].starts_with("Missing value"), "{}", messages[1]);
+    assert!(!messages[1].contains("error="), "{}", messages[1]);
+
+    if cfg!(feature = "location") {
+        assert!(messages.iter().all(|m| m.contains(&format!("location={}", file!()))), "{:?}", messages);
+    }
+}
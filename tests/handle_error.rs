@@ -0,0 +1,76 @@
+use handle_error::handle_error;
+
+fn fails<E>(e: E) -> Result<u32, E> {
+    Err(e)
+}
+
+#[test]
+fn propagates_errors_not_implementing_error() {
+    fn string() -> Result<u32, String> {
+        let v = handle_error!(fails("nope".to_string()), "Failed with string"; code = 3);
+        Ok(v)
+    }
+
+    fn unit() -> Result<u32, ()> {
+        let v = handle_error!(fails(()), "Failed with unit");
+        Ok(v)
+    }
+
+    fn display() -> Result<u32, String> {
+        let v = handle_error!(fails("nope".to_string()), display, "Failed with string");
+        Ok(v)
+    }
+
+    assert_eq!(string(), Err("nope".to_string()));
+    assert_eq!(unit(), Err(()));
+    assert_eq!(display(), Err("nope".to_string()));
+}
+
+#[test]
+fn fallback_modes_precede_the_message() {
+    let v = handle_error!(fails(()), default = 3, "Failed");
+    assert_eq!(v, 3);
+
+    let v = handle_error!(fails("nope"), display, or_else = || 4, "Failed with {}", "context"; code = 1)
+        .to_string();
+    assert_eq!(v, "4");
+}
+
+// defmt does not support named format arguments
+#[test]
+#[cfg(not(feature = "defmt"))]
+fn named_format_arguments_are_not_options() {
+    fn named() -> Result<u32, ()> {
+        let v = handle_error!(fails(()), "Failed {default} {or_else}", default = 5, or_else = 6);
+        Ok(v)
+    }
+
+    assert_eq!(named(), Err(()));
+}
+
+#[test]
+fn long_argument_lists() {
+    struct S {
+        a: (u32, u32),
+    }
+    let s = S { a: (1, 2) };
+
+    fn long(s: &S) -> Result<u32, ()> {
+        let v = handle_error!(fails(()), "failed {} {} {} {} {} {} {} {} {} {} {} {}",
+            s.a.0 + 1, s.a.1 + 1, s.a.0 + 2, s.a.1 + 2, s.a.0 + 3, s.a.1 + 3,
+            s.a.0 + 4, s.a.1 + 4, s.a.0 + 5, s.a.1 + 5, s.a.0 + 6, s.a.1 + 6; field = s.a.0);
+        Ok(v)
+    }
+
+    assert_eq!(long(&s), Err(()));
+}
+
+#[test]
+fn fields_accept_display_and_debug_markers() {
+    fn open(path: &std::path::Path) -> Result<u32, ()> {
+        let v = handle_error!(fails(()), "Failed to open"; code = 3, path = ?path, name = %"config",);
+        Ok(v)
+    }
+
+    assert_eq!(open(std::path::Path::new("/dev/null")), Err(()));
+}
@@ -0,0 +1,44 @@
+#![cfg(all(feature = "log", not(feature = "tracing")))]
+
+use std::sync::Mutex;
+
+use handle_error::retry_error;
+
+struct CaptureLogger(Mutex<Vec<(log::Level, String)>>);
+
+impl log::Log for CaptureLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.0.lock().unwrap().push((record.level(), record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CaptureLogger = CaptureLogger(Mutex::new(Vec::new()));
+
+#[test]
+fn attempts_not_logged_above_final_failure() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let r: Result<(), &str> = retry_error!(2, Err("nope"), "Failed to do something");
+    assert!(r.is_err());
+
+    let records = LOGGER.0.lock().unwrap().clone();
+    assert_eq!(records.len(), 3);
+
+    // The final failure records why retrying stopped
+    let message = &records[2].1;
+    assert!(message.starts_with("Failed to do something reason=attempt limit reached error=\"nope\""), "{}", message);
+
+    let levels: Vec<_> = records.iter().map(|(l, _)| *l).collect();
+
+    // Failed attempts are logged at warn, or the default level where that is lower
+    let (last, attempts) = levels.split_last().unwrap();
+    let expected = (*last).max(log::Level::Warn);
+    assert!(attempts.iter().all(|l| *l == expected), "{:?}", levels);
+}
@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use handle_error::backoff::Backoff;
+use handle_error::clock::ManualClock;
+use handle_error::retry::{RetryPolicy, StopReason};
+use handle_error::retry_error;
+
+#[test]
+fn saturated_delay_stops_at_deadline() {
+    let clock = ManualClock::new();
+
+    for backoff in [
+        Backoff::constant(Duration::MAX),
+        Backoff::linear(Duration::MAX, Duration::MAX),
+        Backoff::exponential(Duration::from_secs(1), Duration::MAX),
+    ] {
+        let r: Result<(), _> = RetryPolicy::new(100)
+            .backoff(backoff)
+            .deadline(Duration::from_secs(3600))
+            .clock(&clock)
+            .run_all(|| Err("nope"));
+
+        let e = r.unwrap_err();
+        assert_eq!(e.reason(), StopReason::Deadline);
+        assert!(e.attempts().len() <= 100);
+    }
+}
+
+#[test]
+fn message_arguments_borrow_alongside_the_expression() {
+    fn push(v: &mut Vec<u32>) -> Result<u32, &'static str> {
+        v.push(1);
+        Err("nope")
+    }
+
+    let mut v = vec![];
+    let r = retry_error!(2, push(&mut v), "failed after {} items", v.len());
+    assert_eq!(r, Err("nope"));
+    assert_eq!(v.len(), 3);
+}
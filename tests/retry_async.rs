@@ -0,0 +1,39 @@
+#![cfg(any(feature = "tokio", feature = "async-std"))]
+
+use std::time::Duration;
+
+use handle_error::retry_error_async;
+
+#[test]
+#[cfg(feature = "tokio")]
+fn backoff_sleeps_using_the_default_sleep() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .start_paused(true)
+        .build()
+        .unwrap();
+
+    rt.block_on(async {
+        let start = tokio::time::Instant::now();
+        let r: Result<(), &str> = retry_error_async!(3, backoff = Duration::from_secs(1),
+            async { Err("nope") }, "Failed to do something");
+
+        assert_eq!(r, Err("nope"));
+        assert_eq!(start.elapsed(), Duration::from_secs(3));
+    });
+}
+
+#[test]
+#[cfg(feature = "async-std")]
+fn backoff_sleeps_using_async_std() {
+    use handle_error::retry::AsyncStdSleep;
+
+    let start = std::time::Instant::now();
+    let r: Result<(), &str> = futures_executor::block_on(async {
+        retry_error_async!(2, backoff = Duration::from_millis(10), sleep = AsyncStdSleep,
+            async { Err("nope") }, "Failed to do something")
+    });
+
+    assert_eq!(r, Err("nope"));
+    assert!(start.elapsed() >= Duration::from_millis(20));
+}
@@ -0,0 +1,111 @@
+#![cfg(feature = "tracing")]
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+use handle_error::handle_error;
+
+/// Subscriber capturing the fields of each event, tagged with how they were recorded
+#[derive(Clone, Default)]
+struct CaptureSubscriber(Arc<Mutex<Vec<HashMap<String, String>>>>);
+
+struct CaptureVisitor<'a>(&'a mut HashMap<String, String>);
+
+impl<'a> Visit for CaptureVisitor<'a> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), format!("i64:{}", value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), format!("str:{}", value));
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn Error + 'static)) {
+        let mut chain = value.to_string();
+        let mut source = value.source();
+        while let Some(s) = source {
+            chain = format!("{} <- {}", chain, s);
+            source = s.source();
+        }
+        self.0.insert(field.name().to_string(), format!("error:{}", chain));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.insert(field.name().to_string(), format!("debug:{:?}", value));
+    }
+}
+
+impl Subscriber for CaptureSubscriber {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, _span: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = HashMap::new();
+        event.record(&mut CaptureVisitor(&mut fields));
+        self.0.lock().unwrap().push(fields);
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[derive(Debug)]
+struct ReadError(std::io::Error);
+
+impl Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "read failed")
+    }
+}
+
+impl Error for ReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+fn read_device() -> Result<u32, ReadError> {
+    Err(ReadError(ErrorKind::NotFound.into()))
+}
+
+#[test]
+fn records_error_expression_and_fields() {
+    fn read(path: &Path) -> Result<u32, ReadError> {
+        let v = handle_error!(read_device(), chain, "Failed to read"; code = 3, path = ?path, name = %"config");
+        Ok(v)
+    }
+
+    let subscriber = CaptureSubscriber::default();
+    let path = Path::new("/dev/null");
+    let r = tracing::subscriber::with_default(subscriber.clone(), || read(path));
+    assert!(r.is_err());
+
+    let events = subscriber.0.lock().unwrap();
+    assert_eq!(events.len(), 1);
+    let fields = &events[0];
+
+    assert_eq!(fields["message"], "debug:Failed to read");
+    assert_eq!(fields["error"], "error:read failed <- entity not found");
+    assert_eq!(fields["expr"], "str:read_device()");
+    assert_eq!(fields["code"], "i64:3");
+    assert_eq!(fields["path"], format!("debug:{:?}", path));
+    assert_eq!(fields["name"], "debug:config");
+}